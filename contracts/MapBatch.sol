@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.13;
+
+/// Map (see Map.json) extended with `saveMany`, so the aggregator can publish
+/// several blob pointers in a single transaction. Emits one `Blob` event per
+/// pointer, in input order.
+contract MapBatch {
+    address owner;
+    uint256 public id;
+    mapping(uint256 => bytes) map;
+
+    event Blob(uint256 indexed id, bytes b);
+
+    constructor(address o) {
+        owner = o;
+    }
+
+    modifier onlyOwner() {
+        require(msg.sender == owner, "not owner");
+        _;
+    }
+
+    function setOwner(address o) external onlyOwner {
+        owner = o;
+    }
+
+    function get(uint256 _id) external view returns (bytes memory) {
+        return map[_id];
+    }
+
+    function save(bytes calldata b) public onlyOwner returns (uint256) {
+        uint256 _id = id++;
+        map[_id] = b;
+        emit Blob(_id, b);
+        return _id;
+    }
+
+    function saveMany(bytes[] calldata bs) external onlyOwner returns (uint256[] memory ids) {
+        ids = new uint256[](bs.length);
+        for (uint256 i = 0; i < bs.length; i++) {
+            ids[i] = save(bs[i]);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{future::Future, sync::Mutex, time::Duration};
+
+use async_graphql::SimpleObject;
+use serde::Serialize;
+use serde_json::json;
+use tokio::time::timeout;
+
+use crate::{avail, billing, celestia, near, retry::Backend, unix_time, ApiContext};
+
+/// How long one balance lookup may take.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A paying account's balance as of the last check.
+#[derive(SimpleObject, Serialize, Clone)]
+pub(crate) struct AccountBalance {
+    pub(crate) backend: Backend,
+    /// Address or account id; `null` for the Celestia node's own wallet.
+    pub(crate) address: Option<String>,
+    /// Decimal integer in `unit`; `null` when the lookup failed.
+    pub(crate) balance: Option<String>,
+    /// `plank` (Avail), `yoctoNEAR`, `utia` or `wei`.
+    pub(crate) unit: String,
+    /// `balance` in whole tokens.
+    pub(crate) tokens: Option<f64>,
+    /// Alert threshold in whole tokens, from `accounts.min_balance`.
+    pub(crate) min_balance: Option<f64>,
+    /// Whether `tokens` is below `min_balance`.
+    pub(crate) low: bool,
+    /// Why the balance could not be read.
+    pub(crate) error: Option<String>,
+    /// Unix seconds.
+    pub(crate) checked_at: u64,
+}
+
+impl AccountBalance {
+    fn same_account(&self, other: &AccountBalance) -> bool {
+        self.backend == other.backend && self.address == other.address
+    }
+}
+
+/// Latest balances, refreshed every `accounts.interval_secs`.
+#[derive(Default)]
+pub(crate) struct Accounts {
+    balances: Mutex<Vec<AccountBalance>>,
+}
+
+impl Accounts {
+    pub(crate) fn latest(&self) -> Vec<AccountBalance> {
+        self.balances.lock().unwrap().clone()
+    }
+}
+
+/// Checks balances forever, alerting when one drops below its threshold.
+pub(crate) async fn run(api_context: ApiContext) {
+    let period = Duration::from_secs(api_context.settings.accounts.interval_secs);
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        refresh(&api_context).await;
+    }
+}
+
+/// Looks up every balance now and sends the webhook for each account that
+/// has just gone low. An account stays quiet until it recovers and drops
+/// again.
+pub(crate) async fn refresh(api_context: &ApiContext) -> Vec<AccountBalance> {
+    let balances = check(api_context).await;
+    let previous = std::mem::replace(
+        &mut *api_context.accounts.balances.lock().unwrap(),
+        balances.clone(),
+    );
+
+    for balance in balances.iter().filter(|balance| balance.low) {
+        let was_low = previous
+            .iter()
+            .any(|before| before.same_account(balance) && before.low);
+        if was_low {
+            continue;
+        }
+        eprintln!(
+            "{} account {} is below its minimum balance: {} {}",
+            balance.backend.name(),
+            balance.address.as_deref().unwrap_or("(node wallet)"),
+            balance.balance.as_deref().unwrap_or_default(),
+            balance.unit
+        );
+        if let Some(url) = &api_context.settings.accounts.webhook {
+            if let Err(e) = notify(url, balance).await {
+                eprintln!("Low balance webhook failed: {}", e);
+            }
+        }
+    }
+    balances
+}
+
+async fn check(api_context: &ApiContext) -> Vec<AccountBalance> {
+    let settings = &api_context.settings;
+    // Each distinct NEAR account, tenants' included.
+    let mut near_accounts = vec![&settings.near];
+    for tenant in settings.tenants.values().filter_map(|t| t.near.as_ref()) {
+        if !near_accounts
+            .iter()
+            .any(|a| a.account_id == tenant.account_id && a.network == tenant.network)
+        {
+            near_accounts.push(tenant);
+        }
+    }
+
+    let (avail, celestia, registry) = tokio::join!(
+        lookup(api_context, Backend::Avail, "plank", async {
+            avail::balance()
+                .await
+                .map(|(address, balance)| Some((Some(address), balance)))
+        }),
+        lookup(api_context, Backend::Celestia, "utia", async {
+            celestia::balance()
+                .await
+                .map(|balance| Some((None, balance)))
+        }),
+        lookup(api_context, Backend::Registry, "wei", async {
+            match api_context.registry.balance().await {
+                Some(balance) => balance.map(|(address, balance)| Some((Some(address), balance))),
+                None => Ok(None),
+            }
+        }),
+    );
+    let mut balances: Vec<_> = [avail, celestia, registry].into_iter().flatten().collect();
+    for account in near_accounts {
+        let near = lookup(api_context, Backend::Near, "yoctoNEAR", async {
+            near::balance(account)
+                .await
+                .map(|balance| Some((Some(account.account_id.clone()), balance)))
+        })
+        .await;
+        balances.extend(near);
+    }
+    balances
+}
+
+/// Runs a balance lookup unless `backend` is in `accounts.skip`. Lookups
+/// that answer `Ok(None)` have no account to report.
+async fn lookup(
+    api_context: &ApiContext,
+    backend: Backend,
+    unit: &str,
+    balance: impl Future<Output = Result<Option<(Option<String>, u128)>, String>>,
+) -> Option<AccountBalance> {
+    let settings = &api_context.settings.accounts;
+    if settings.skip.iter().any(|name| name == backend.name()) {
+        return None;
+    }
+    let min_balance = settings.min_balance.get(backend.name()).copied();
+    let mut account = AccountBalance {
+        backend,
+        address: None,
+        balance: None,
+        unit: unit.to_string(),
+        tokens: None,
+        min_balance,
+        low: false,
+        error: None,
+        checked_at: unix_time(),
+    };
+    let result = timeout(LOOKUP_TIMEOUT, balance)
+        .await
+        .unwrap_or_else(|_| Err("Balance lookup timed out".to_string()));
+    match result {
+        Ok(Some((address, balance))) => {
+            let tokens =
+                billing::token(unit).map(|(_, decimals)| balance as f64 / 10f64.powi(decimals));
+            account.address = address;
+            account.balance = Some(balance.to_string());
+            account.tokens = tokens;
+            account.low = tokens.zip(min_balance).is_some_and(|(t, min)| t < min);
+        }
+        Ok(None) => return None,
+        Err(e) => account.error = Some(e),
+    }
+    Some(account)
+}
+
+async fn notify(url: &str, balance: &AccountBalance) -> Result<(), String> {
+    reqwest::Client::new()
+        .post(url)
+        .json(&json!({ "event": "low_balance", "account": balance }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
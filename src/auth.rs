@@ -0,0 +1,137 @@
+use async_graphql::Context;
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use ethers::utils::hex;
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::{config::Settings, hash_data, ApiContext, Obj};
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Who is making a GraphQL request, attached to each request by the HTTP
+/// handler. `tenant` is `None` for unauthenticated callers.
+#[derive(Clone, Default)]
+pub(crate) struct Caller {
+    pub(crate) tenant: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    /// The tenant the token was issued to.
+    sub: String,
+}
+
+/// Resolves the tenant from an `x-api-key` header or an `Authorization:
+/// Bearer` API key or HS256 JWT whose `sub` is a configured tenant. No
+/// credentials gives an anonymous caller; credentials that do not check out
+/// are an error.
+pub(crate) fn authenticate(settings: &Settings, headers: &HeaderMap) -> Result<Caller, String> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    let Some(credential) = api_key.or(bearer) else {
+        return Ok(Caller::default());
+    };
+    if let Some(tenant) = tenant_for_key(settings, credential) {
+        return Ok(Caller {
+            tenant: Some(tenant),
+        });
+    }
+    match (&settings.auth.jwt_secret, bearer) {
+        (Some(secret), Some(token)) => {
+            let claims = decode::<Claims>(
+                token,
+                &DecodingKey::from_secret(secret.as_bytes()),
+                &Validation::new(Algorithm::HS256),
+            )
+            .map_err(|e| format!("Invalid token: {}", e))?
+            .claims;
+            if !settings.tenants.contains_key(&claims.sub) {
+                return Err(format!("Invalid token: unknown tenant {}", claims.sub));
+            }
+            Ok(Caller {
+                tenant: Some(claims.sub),
+            })
+        }
+        _ => Err("Invalid API key".to_string()),
+    }
+}
+
+/// Tenants list the sha256 (hex) of their keys rather than the keys.
+fn tenant_for_key(settings: &Settings, key: &str) -> Option<String> {
+    let hash = hex::encode(hash_data(key.as_bytes()));
+    settings
+        .tenants
+        .iter()
+        .find(|(_, tenant)| {
+            tenant
+                .api_keys
+                .iter()
+                .any(|k| k.trim_start_matches("0x").eq_ignore_ascii_case(&hash))
+        })
+        .map(|(name, _)| name.clone())
+}
+
+pub(crate) fn caller<'a>(ctx: &'a Context<'_>) -> &'a Caller {
+    static ANONYMOUS: Caller = Caller { tenant: None };
+    ctx.data_opt::<Caller>().unwrap_or(&ANONYMOUS)
+}
+
+/// The tenant a write is made for. Fails for anonymous callers unless
+/// `auth.required` is turned off.
+pub(crate) fn writer(ctx: &Context<'_>) -> async_graphql::Result<Option<String>> {
+    let api_context = ctx.data_unchecked::<ApiContext>();
+    match &caller(ctx).tenant {
+        Some(tenant) => Ok(Some(tenant.clone())),
+        None if !api_context.settings.auth.required => Ok(None),
+        None => Err("Authentication required".into()),
+    }
+}
+
+/// Fails unless the caller is one of `auth.operators`. Anonymous callers pass
+/// when `auth.required` is off.
+pub(crate) fn operator(ctx: &Context<'_>) -> async_graphql::Result<()> {
+    let settings = &ctx.data_unchecked::<ApiContext>().settings.auth;
+    match &caller(ctx).tenant {
+        Some(tenant) if settings.operators.contains(tenant) => Ok(()),
+        None if !settings.required => Ok(()),
+        None => Err("Authentication required".into()),
+        Some(_) => Err("Only operators can read this".into()),
+    }
+}
+
+/// The tenant whose records a listing or report covers. Tenants only ever
+/// see their own; anonymous callers may pick any tenant, or all of them with
+/// `None`, when `auth.required` is off.
+pub(crate) fn scope(
+    settings: &Settings,
+    caller: &Caller,
+    asked: Option<String>,
+) -> Result<Option<String>, String> {
+    match (&caller.tenant, asked) {
+        (Some(own), Some(asked)) if asked != *own => {
+            Err("Cannot read another tenant's records".to_string())
+        }
+        (Some(own), _) => Ok(Some(own.clone())),
+        (None, asked) if !settings.auth.required => Ok(asked),
+        (None, _) => Err("Authentication required".to_string()),
+    }
+}
+
+/// Whether the caller may see a blob record: its own, or, for operators, ones
+/// stored without a tenant (including every blob the indexer recorded, whose
+/// tenant is not known). Anonymous callers see everything when
+/// `auth.required` is off.
+pub(crate) fn can_see(ctx: &Context<'_>, obj: &Obj) -> bool {
+    let settings = &ctx.data_unchecked::<ApiContext>().settings.auth;
+    match &caller(ctx).tenant {
+        None => !settings.required,
+        Some(tenant) if obj.tenant.as_ref() == Some(tenant) => true,
+        Some(tenant) => obj.tenant.is_none() && settings.operators.contains(tenant),
+    }
+}
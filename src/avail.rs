@@ -0,0 +1,284 @@
+use avail_subxt::{
+    api::{
+        self,
+        runtime_types::{
+            bounded_collections::bounded_vec::BoundedVec, da_control::pallet::Call as DaCall,
+        },
+    },
+    avail::{AppUncheckedExtrinsic, Pair},
+    build_client,
+    primitives::AvailExtrinsicParams,
+    Call,
+};
+use sp_core::{blake2_256, Pair as _, H256};
+use subxt::{tx::PairSigner, utils::AccountId32};
+
+use crate::{
+    billing::Fee,
+    model::DA,
+    retry::{Attempt, Failure, Landed},
+    unix_time, AVAIL_SEED, AVAIL_SERVER,
+};
+
+/// Largest `submit_data` payload the runtime accepts (`MaxAppDataLength`).
+pub(crate) const MAX_BLOB_SIZE: usize = 512 * 1024;
+
+/// Fee the runtime would charge now for submitting `size` bytes, from
+/// `payment_queryInfo` on a signed but unsent extrinsic.
+pub(crate) async fn estimate(size: usize) -> Result<u128, String> {
+    if size > MAX_BLOB_SIZE {
+        return Err(too_large(size));
+    }
+    let client = build_client(AVAIL_SERVER, true)
+        .await
+        .map_err(|e| format!("Failed to connect to Avail: {}", e))?;
+    let pair = Pair::from_string_with_seed(AVAIL_SEED, None)
+        .map_err(|e| format!("Invalid Avail seed: {:?}", e))?;
+    let signer = PairSigner::new(pair.0);
+
+    let data_transfer = api::tx()
+        .data_availability()
+        .submit_data(BoundedVec(vec![0; size]));
+    let extrinsic_params = AvailExtrinsicParams::new_with_app_id(1.into());
+
+    client
+        .tx()
+        .create_signed(&data_transfer, &signer, extrinsic_params)
+        .await
+        .map_err(|e| format!("Could not build Avail extrinsic: {}", e))?
+        .partial_fee_estimate()
+        .await
+        .map_err(|e| format!("Avail fee query failed: {}", e))
+}
+
+/// Submits `data` and waits for finality. Returns the registry pointer,
+/// `DA::Avail | block_hash[32] | extrinsic_index[4 LE]`, and the fee from the
+/// extrinsic's `TransactionFeePaid` event, when it has one. The extrinsic is
+/// signed first and handed to `sent` before it is broadcast.
+pub(crate) async fn submit(
+    data: Vec<u8>,
+    sent: impl FnOnce(Attempt),
+) -> Result<(Vec<u8>, Option<Fee>), Failure> {
+    if data.len() > MAX_BLOB_SIZE {
+        return Err(Failure::Invalid(too_large(data.len())));
+    }
+    let client = build_client(AVAIL_SERVER, true)
+        .await
+        .map_err(|e| Failure::Transient(format!("Failed to connect to Avail: {}", e)))?;
+    let pair = Pair::from_string_with_seed(AVAIL_SEED, None)
+        .map_err(|e| Failure::Invalid(format!("Invalid Avail seed: {:?}", e)))?;
+    let account = AccountId32::from(pair.0.public().0);
+    let signer = PairSigner::new(pair.0);
+
+    let nonce = client
+        .tx()
+        .account_nonce(&account)
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail nonce lookup failed: {}", e)))?;
+    let head = client
+        .rpc()
+        .finalized_head()
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail finalized head unavailable: {}", e)))?;
+    let height = client
+        .rpc()
+        .header(Some(head))
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail header lookup failed: {}", e)))?
+        .ok_or_else(|| Failure::Transient("Avail finalized header not found".to_string()))?
+        .number;
+
+    let data_transfer = api::tx().data_availability().submit_data(BoundedVec(data));
+    let extrinsic_params = AvailExtrinsicParams::new_with_app_id(1.into());
+    let extrinsic = client
+        .tx()
+        .create_signed_with_nonce(&data_transfer, &signer, nonce, extrinsic_params)
+        .map_err(|e| Failure::Invalid(format!("Could not build Avail extrinsic: {}", e)))?;
+    sent(Attempt {
+        tx_hash: Some(format!("{:?}", H256(blake2_256(extrinsic.encoded())))),
+        height: Some(height.into()),
+        nonce: Some(nonce),
+        ..Attempt::new(DA::Avail)
+    });
+
+    let h = extrinsic
+        .submit_and_watch()
+        .await
+        .map_err(|e| Failure::Final(format!("Avail submission failed: {}", e)))?
+        .wait_for_finalized_success()
+        .await
+        .map_err(|e| Failure::Final(format!("Avail submission did not finalize: {}", e)))?;
+
+    let fee = match h.find_first::<api::transaction_payment::events::TransactionFeePaid>() {
+        Ok(event) => event.map(|paid| Fee::da(paid.actual_fee, "plank")),
+        Err(e) => {
+            eprintln!("Could not decode Avail fee event: {}", e);
+            None
+        }
+    };
+
+    Ok((pointer(h.block_hash(), h.extrinsic_index()), fee))
+}
+
+/// Looks for the extrinsic of `attempt` in the finalized blocks above the one
+/// it was signed at. Once the account's nonce has moved past the one it was
+/// signed with, it can no longer be included.
+pub(crate) async fn find(attempt: &Attempt) -> Result<Landed<Vec<u8>>, Failure> {
+    let (Some(tx_hash), Some(from), Some(nonce)) =
+        (&attempt.tx_hash, attempt.height, attempt.nonce)
+    else {
+        return Ok(Landed::Unknown(
+            "Avail attempt has no transaction recorded".to_string(),
+        ));
+    };
+    let tx_hash: H256 = tx_hash
+        .parse()
+        .map_err(|_| Failure::Invalid("Malformed Avail transaction hash".to_string()))?;
+    let client = build_client(AVAIL_SERVER, true)
+        .await
+        .map_err(|e| Failure::Transient(format!("Failed to connect to Avail: {}", e)))?;
+    let pair = Pair::from_string_with_seed(AVAIL_SEED, None)
+        .map_err(|e| Failure::Invalid(format!("Invalid Avail seed: {:?}", e)))?;
+    let account = AccountId32::from(pair.0.public().0);
+
+    let head = client
+        .rpc()
+        .finalized_head()
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail finalized head unavailable: {}", e)))?;
+    let head_number: u64 = client
+        .rpc()
+        .header(Some(head))
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail header lookup failed: {}", e)))?
+        .ok_or_else(|| Failure::Transient("Avail finalized header not found".to_string()))?
+        .number
+        .into();
+    for number in from + 1..=head_number {
+        let hash = client
+            .rpc()
+            .block_hash(Some(number.into()))
+            .await
+            .map_err(|e| Failure::Transient(format!("Avail block lookup failed: {}", e)))?
+            .ok_or_else(|| Failure::Transient(format!("Avail block {} not found", number)))?;
+        let block = client
+            .rpc()
+            .block(Some(hash))
+            .await
+            .map_err(|e| Failure::Transient(format!("Avail block lookup failed: {}", e)))?
+            .ok_or_else(|| Failure::Transient(format!("Avail block {} not found", number)))?;
+        let index = block
+            .block
+            .extrinsics
+            .iter()
+            .position(|ext| H256(blake2_256(&ext.0)) == tx_hash);
+        if let Some(index) = index {
+            return Ok(Landed::Yes(pointer(hash, index as u32)));
+        }
+    }
+
+    let next_nonce = client
+        .storage()
+        .at(head)
+        .fetch(&api::storage().system().account(&account))
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail nonce lookup failed: {}", e)))?
+        .map_or(0, |info| info.nonce);
+    if u64::from(next_nonce) > nonce {
+        Ok(Landed::No)
+    } else {
+        Ok(Landed::Unknown(format!(
+            "Avail transaction {:?} is not finalized yet",
+            tx_hash
+        )))
+    }
+}
+
+fn too_large(size: usize) -> String {
+    format!(
+        "Avail accepts blobs of at most {} bytes, not {}",
+        MAX_BLOB_SIZE, size
+    )
+}
+
+fn pointer(block_hash: H256, extrinsic_index: u32) -> Vec<u8> {
+    let mut ptr = vec![DA::Avail as u8];
+    ptr.extend_from_slice(block_hash.as_bytes());
+    ptr.extend_from_slice(&extrinsic_index.to_le_bytes());
+    ptr
+}
+
+/// SS58 address of the submitting account and its free balance in plank.
+pub(crate) async fn balance() -> Result<(String, u128), String> {
+    let client = build_client(AVAIL_SERVER, true)
+        .await
+        .map_err(|e| format!("Failed to connect to Avail: {}", e))?;
+    let pair = Pair::from_string_with_seed(AVAIL_SEED, None)
+        .map_err(|e| format!("Invalid Avail seed: {:?}", e))?;
+    let account = AccountId32::from(pair.0.public().0);
+
+    let info = client
+        .storage()
+        .at_latest()
+        .await
+        .map_err(|e| format!("Avail storage unavailable: {}", e))?
+        .fetch(&api::storage().system().account(&account))
+        .await
+        .map_err(|e| format!("Avail balance lookup failed: {}", e))?;
+    Ok((account.to_string(), info.map_or(0, |info| info.data.free)))
+}
+
+/// Seconds since the finalized head was produced, by its `Timestamp.Now`.
+pub(crate) async fn finalized_head_age() -> Result<u64, String> {
+    let client = build_client(AVAIL_SERVER, true)
+        .await
+        .map_err(|e| format!("Failed to connect to Avail: {}", e))?;
+    let head = client
+        .rpc()
+        .finalized_head()
+        .await
+        .map_err(|e| format!("Avail finalized head unavailable: {}", e))?;
+    let millis = client
+        .storage()
+        .at(head)
+        .fetch(&api::storage().timestamp().now())
+        .await
+        .map_err(|e| format!("Avail block timestamp unavailable: {}", e))?
+        .ok_or("Avail finalized head has no timestamp")?;
+    Ok(unix_time().saturating_sub(millis / 1000))
+}
+
+pub(crate) async fn get(ptr: &[u8]) -> Result<Vec<u8>, Failure> {
+    if ptr.len() != 37 {
+        return Err(Failure::Invalid("Malformed Avail pointer".to_string()));
+    }
+    let hash: [u8; 32] = ptr[1..33].try_into().unwrap();
+    let index: [u8; 4] = ptr[33..37].try_into().unwrap();
+    let client = build_client(AVAIL_SERVER, true)
+        .await
+        .map_err(|e| Failure::Transient(format!("Failed to connect to Avail: {}", e)))?;
+
+    let submitted_block = client
+        .rpc()
+        .block(Some(H256::from(hash)))
+        .await
+        .map_err(|e| Failure::Transient(format!("Avail block lookup failed: {}", e)))?
+        .ok_or_else(|| Failure::Transient("Avail block not found".to_string()))?;
+
+    let call = submitted_block
+        .block
+        .extrinsics
+        .into_iter()
+        .nth(u32::from_le_bytes(index) as usize)
+        .and_then(|chain_block_ext| {
+            AppUncheckedExtrinsic::try_from(chain_block_ext)
+                .map(|ext| ext.function)
+                .ok()
+        })
+        .ok_or_else(|| Failure::Invalid("Avail extrinsic not found".to_string()))?;
+
+    match call {
+        Call::DataAvailability(DaCall::submit_data { data }) => Ok(data.0),
+        _ => Ok(vec![]),
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+
+use crate::{model::DA, registry::RegistryTx, ApiContext};
+
+/// A fee paid for a blob, in the smallest unit of the chain it was paid on.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Fee {
+    /// `da` for the DA submission, `registry` for publishing the pointer.
+    pub(crate) kind: String,
+    /// Decimal integer; yoctoNEAR and wei amounts do not fit in 64 bits.
+    pub(crate) amount: String,
+    /// `utia`, `plank` (Avail), `yoctoNEAR` or `wei`.
+    pub(crate) unit: String,
+}
+
+impl Fee {
+    pub(crate) fn da(amount: u128, unit: &str) -> Self {
+        Fee {
+            kind: "da".to_string(),
+            amount: amount.to_string(),
+            unit: unit.to_string(),
+        }
+    }
+
+    /// The fee charged for a registry transaction, when it is known.
+    pub(crate) fn registry(tx: Option<&RegistryTx>) -> Option<Self> {
+        tx.and_then(|tx| tx.fee.clone()).map(|amount| Fee {
+            kind: "registry".to_string(),
+            amount,
+            unit: "wei".to_string(),
+        })
+    }
+}
+
+/// The token a fee unit belongs to and its decimals, to express amounts in
+/// whole tokens.
+pub(crate) fn token(unit: &str) -> Option<(&'static str, i32)> {
+    match unit {
+        "utia" => Some(("TIA", 6)),
+        "plank" => Some(("AVAIL", 18)),
+        "yoctoNEAR" => Some(("NEAR", 24)),
+        "wei" => Some(("ETH", 18)),
+        _ => None,
+    }
+}
+
+/// What a tenant stored on one DA during one UTC day, and what it cost.
+#[derive(SimpleObject)]
+pub(crate) struct DailyUsage {
+    /// `YYYY-MM-DD`, UTC.
+    date: String,
+    tenant: Option<String>,
+    da: DA,
+    blobs: u64,
+    bytes: u64,
+    /// Fees summed by kind and unit. Failed submissions count too: their
+    /// fees were still paid.
+    fees: Vec<Fee>,
+}
+
+/// Usage by day, tenant and DA for blobs created between `from` and `to`
+/// (unix seconds, inclusive), for `tenant` or for every tenant when `None`.
+pub(crate) fn usage(
+    api_context: &ApiContext,
+    tenant: Option<&str>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Vec<DailyUsage>, String> {
+    let totals = api_context
+        .index
+        .daily_totals(tenant, from, to)
+        .map_err(|e| format!("Could not read usage: {}", e))?;
+    Ok(totals
+        .into_iter()
+        .filter_map(|totals| {
+            Some(DailyUsage {
+                date: date(totals.day),
+                tenant: totals.tenant,
+                da: DA::from_tag(totals.da)?,
+                blobs: totals.blobs,
+                bytes: totals.bytes,
+                fees: totals
+                    .fees
+                    .into_iter()
+                    .map(|((kind, unit), amount)| Fee {
+                        kind,
+                        amount: amount.to_string(),
+                        unit,
+                    })
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+/// Sums fees by kind and unit; amounts that do not parse are skipped.
+pub(crate) fn add_fee(totals: &mut BTreeMap<(String, String), u128>, fee: &Fee) {
+    match fee.amount.parse::<u128>() {
+        Ok(amount) => {
+            let total = totals
+                .entry((fee.kind.clone(), fee.unit.clone()))
+                .or_default();
+            *total = total.saturating_add(amount);
+        }
+        Err(_) => eprintln!("Skipping unreadable fee amount {}", fee.amount),
+    }
+}
+
+/// `usage` as CSV, one line per tenant, DA and day. Each kind of fee gets an
+/// amount and a unit column.
+pub(crate) fn csv(usage: &[DailyUsage]) -> String {
+    let mut out = String::from(
+        "date,tenant,da,blobs,bytes,da_fee,da_fee_unit,registry_fee,registry_fee_unit\n",
+    );
+    for row in usage {
+        let fees = |kind: &str| {
+            let fees: Vec<_> = row.fees.iter().filter(|fee| fee.kind == kind).collect();
+            let amounts: Vec<_> = fees.iter().map(|fee| fee.amount.as_str()).collect();
+            let units: Vec<_> = fees.iter().map(|fee| fee.unit.as_str()).collect();
+            (amounts.join(";"), units.join(";"))
+        };
+        let (da_fee, da_unit) = fees("da");
+        let (registry_fee, registry_unit) = fees("registry");
+        let fields = [
+            row.date.clone(),
+            row.tenant.clone().unwrap_or_default(),
+            row.da.name().to_string(),
+            row.blobs.to_string(),
+            row.bytes.to_string(),
+            da_fee,
+            da_unit,
+            registry_fee,
+            registry_unit,
+        ];
+        let line: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// `YYYY-MM-DD` of a count of days since the unix epoch.
+fn date(day: u64) -> String {
+    // Howard Hinnant's civil_from_days.
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
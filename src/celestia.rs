@@ -0,0 +1,210 @@
+use celestia_rpc::{BlobClient, Client as Tia, HeaderClient, StateClient};
+use celestia_types::{
+    blob::SubmitOptions, nmt::Namespace as TiaNamespace, Blob as TiaBlob, Commitment,
+};
+
+use crate::{
+    billing::Fee,
+    config::CelestiaSettings,
+    model::DA,
+    retry::{Attempt, Failure, Landed},
+    TIA_AUTH_TOKEN, TIA_SERVER,
+};
+
+// Gas schedule of a PayForBlobs transaction in celestia-app.
+const SHARE_SIZE: u64 = 512;
+const FIRST_SHARE_CONTENT: u64 = 478;
+const CONTINUATION_SHARE_CONTENT: u64 = 482;
+const GAS_PER_BLOB_BYTE: u64 = 8;
+const TX_SIZE_COST_PER_BYTE: u64 = 10;
+const BYTES_PER_BLOB_INFO: u64 = 70;
+const PFB_GAS_FIXED_COST: u64 = 75_000;
+
+/// Blocks a transaction stays in a celestia-app mempool (`ttl-num-blocks`)
+/// before it is dropped.
+const MEMPOOL_TTL_BLOCKS: u64 = 5;
+
+fn one_bytes_array<const N: usize>() -> [u8; N] {
+    [1; N]
+}
+
+/// Gas celestia-app charges for a PayForBlobs with one blob of `size` bytes.
+pub(crate) fn gas_limit(size: u64) -> u64 {
+    let shares = if size <= FIRST_SHARE_CONTENT {
+        1
+    } else {
+        1 + (size - FIRST_SHARE_CONTENT).div_ceil(CONTINUATION_SHARE_CONTENT)
+    };
+    shares * SHARE_SIZE * GAS_PER_BLOB_BYTE
+        + TX_SIZE_COST_PER_BYTE * BYTES_PER_BLOB_INFO
+        + PFB_GAS_FIXED_COST
+}
+
+/// Fee in utia for `gas` at `gas_price`.
+pub(crate) fn fee(gas: u64, gas_price: f64) -> u64 {
+    (gas as f64 * gas_price).ceil() as u64
+}
+
+/// Submits `data` to the Celestia node and returns its registry pointer,
+/// `DA::Celestia | height[8 LE] | commitment[32]`, and the fee paid. The gas
+/// limit and fee are set explicitly at `celestia.gas_price`: Cosmos chains
+/// charge the whole fee whatever gas is used, so this is the amount paid.
+/// `blob.Submit` only returns the height, so without a configured price the
+/// fee could not be known and nothing is submitted.
+pub(crate) async fn submit(
+    settings: &CelestiaSettings,
+    data: Vec<u8>,
+) -> Result<(Vec<u8>, Fee), Failure> {
+    let price = settings.gas_price.ok_or_else(|| {
+        Failure::Invalid("Celestia is disabled: celestia.gas_price is not set".to_string())
+    })?;
+    let gas = gas_limit(data.len() as u64);
+    let fee = fee(gas, price);
+    let blob = [blob(data)?];
+    let client = connect().await?;
+    let options = SubmitOptions {
+        fee: Some(fee),
+        gas_limit: Some(gas),
+    };
+    let height = client
+        .blob_submit(&blob, options)
+        .await
+        .map_err(|e| Failure::Final(format!("Celestia submission failed: {}", e)))?;
+
+    Ok((pointer(height, &blob[0]), Fee::da(fee.into(), "utia")))
+}
+
+/// Height the node has synced to. A blob submitted from now on lands above
+/// it.
+pub(crate) async fn height() -> Result<u64, Failure> {
+    let client = connect().await?;
+    let sync = client
+        .header_sync_state()
+        .await
+        .map_err(|e| Failure::Transient(format!("Celestia sync state unavailable: {}", e)))?;
+    Ok(sync.height)
+}
+
+/// Looks for `data` in the blocks above the height `attempt` was made at,
+/// by its commitment. Once the chain is `MEMPOOL_TTL_BLOCKS` past that
+/// height without it, the transaction has been dropped.
+pub(crate) async fn find(data: &[u8], attempt: &Attempt) -> Result<Landed<Vec<u8>>, Failure> {
+    let Some(from) = attempt.height else {
+        return Ok(Landed::Unknown(
+            "Celestia attempt has no starting height".to_string(),
+        ));
+    };
+    let blob = blob(data.to_vec())?;
+    let client = connect().await?;
+    let head = client
+        .header_sync_state()
+        .await
+        .map_err(|e| Failure::Transient(format!("Celestia sync state unavailable: {}", e)))?
+        .height;
+    for height in from + 1..=head {
+        let found = client
+            .blob_get(
+                height,
+                TiaNamespace::const_v0(one_bytes_array()),
+                Commitment(blob.commitment.0),
+            )
+            .await;
+        match found {
+            Ok(_) => return Ok(Landed::Yes(pointer(height, &blob))),
+            Err(e) if e.to_string().contains("not found") => {}
+            Err(e) => {
+                return Err(Failure::Transient(format!(
+                    "Celestia blob lookup failed: {}",
+                    e
+                )))
+            }
+        }
+    }
+    if head >= from + MEMPOOL_TTL_BLOCKS {
+        Ok(Landed::No)
+    } else {
+        Ok(Landed::Unknown(format!(
+            "Celestia blob not included by height {}, sent at {}",
+            head, from
+        )))
+    }
+}
+
+fn blob(data: Vec<u8>) -> Result<TiaBlob, Failure> {
+    TiaBlob::new(TiaNamespace::const_v0(one_bytes_array()), data)
+        .map_err(|e| Failure::Invalid(format!("Invalid Celestia blob: {}", e)))
+}
+
+/// Registry pointer of `blob` included at `height`.
+fn pointer(height: u64, blob: &TiaBlob) -> Vec<u8> {
+    let mut ptr = vec![DA::Celestia as u8];
+    ptr.extend_from_slice(&height.to_le_bytes());
+    ptr.extend_from_slice(&blob.commitment.0);
+    ptr
+}
+
+async fn connect() -> Result<Tia, Failure> {
+    Tia::new(TIA_SERVER, Some(TIA_AUTH_TOKEN))
+        .await
+        .map_err(|e| Failure::Transient(format!("Failed to connect to Celestia node: {}", e)))
+}
+
+/// What the Celestia node reports about itself, for `/ready`.
+pub(crate) struct NodeStatus {
+    /// Height the node has synced headers to.
+    pub(crate) height: u64,
+    /// Network head the node is syncing towards.
+    pub(crate) network_height: u64,
+    /// utia held by the node's account, which pays for submissions.
+    pub(crate) balance: u128,
+}
+
+pub(crate) async fn node_status() -> Result<NodeStatus, String> {
+    let client = Tia::new(TIA_SERVER, Some(TIA_AUTH_TOKEN))
+        .await
+        .map_err(|e| format!("Failed to connect to Celestia node: {}", e))?;
+    let sync = client
+        .header_sync_state()
+        .await
+        .map_err(|e| format!("Celestia sync state unavailable: {}", e))?;
+    Ok(NodeStatus {
+        height: sync.height,
+        network_height: sync.to_height,
+        balance: balance().await?,
+    })
+}
+
+/// utia held by the node's account.
+pub(crate) async fn balance() -> Result<u128, String> {
+    let client = Tia::new(TIA_SERVER, Some(TIA_AUTH_TOKEN))
+        .await
+        .map_err(|e| format!("Failed to connect to Celestia node: {}", e))?;
+    let balance = client
+        .state_balance()
+        .await
+        .map_err(|e| format!("Celestia balance unavailable: {}", e))?;
+    balance
+        .amount
+        .to_string()
+        .parse()
+        .map_err(|e| format!("Unreadable Celestia balance: {}", e))
+}
+
+pub(crate) async fn get(ptr: &[u8]) -> Result<Vec<u8>, Failure> {
+    if ptr.len() != 41 {
+        return Err(Failure::Invalid("Malformed Celestia pointer".to_string()));
+    }
+    let height: [u8; 8] = ptr[1..9].try_into().unwrap();
+    let commitment: [u8; 32] = ptr[9..41].try_into().unwrap();
+
+    let client = connect().await?;
+    let blob = client
+        .blob_get(
+            u64::from_le_bytes(height),
+            TiaNamespace::const_v0(one_bytes_array()),
+            Commitment(commitment),
+        )
+        .await
+        .map_err(|e| Failure::Transient(format!("Celestia retrieval failed: {}", e)))?;
+    Ok(blob.data)
+}
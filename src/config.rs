@@ -0,0 +1,624 @@
+use std::{collections::HashMap, env, fs};
+
+use ethers::signers::LocalWallet;
+use serde::Deserialize;
+
+use crate::{
+    eigen::{self, SecurityParam},
+    model::DA,
+    near,
+    retry::Backend,
+    EIGEN_SERVER, NEAR_ACCOUNT_ID, NEAR_SECRET, OPSEP_CONTRACT, OPSEP_RPC,
+};
+
+/// Runtime configuration, read from the JSON file pointed to by `DA_CONFIG`
+/// (default `config.json`). Anything missing falls back to the built-in
+/// testnet defaults.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// SQLite file holding the blob index.
+    pub(crate) database: String,
+    pub(crate) near: NearSettings,
+    pub(crate) celestia: CelestiaSettings,
+    pub(crate) eigenda: EigenDaSettings,
+    pub(crate) registry: RegistrySettings,
+    pub(crate) auth: AuthSettings,
+    /// Limits for tenants without their own `quota`.
+    pub(crate) quota: QuotaSettings,
+    /// Policy for `storeBlob(da: AUTO)` for tenants without their own.
+    pub(crate) routing: RoutingSettings,
+    /// Where failed submissions go next, for tenants without their own.
+    pub(crate) failover: FailoverSettings,
+    /// Retry policy and circuit breaker per backend: `eigenda`, `avail`,
+    /// `near`, `celestia` or `registry`.
+    pub(crate) retry: HashMap<String, RetryPolicy>,
+    /// What `/ready` checks and how long it waits.
+    pub(crate) ready: ReadySettings,
+    /// Balance checks of the accounts that pay for submissions.
+    pub(crate) accounts: AccountSettings,
+    pub(crate) tenants: HashMap<String, TenantSettings>,
+    /// USD price of each fee token (`TIA`, `AVAIL`, `NEAR`, `ETH`), used to
+    /// compare cost estimates across DAs.
+    pub(crate) prices: HashMap<String, f64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            database: "da.sqlite".to_string(),
+            near: NearSettings::default(),
+            celestia: CelestiaSettings::default(),
+            eigenda: EigenDaSettings::default(),
+            registry: RegistrySettings::default(),
+            auth: AuthSettings::default(),
+            quota: QuotaSettings::default(),
+            routing: RoutingSettings::default(),
+            failover: FailoverSettings::default(),
+            retry: HashMap::new(),
+            ready: ReadySettings::default(),
+            accounts: AccountSettings::default(),
+            tenants: HashMap::new(),
+            prices: HashMap::new(),
+        }
+    }
+}
+
+/// How GraphQL callers are identified. Tenants authenticate with one of
+/// their `api_keys` or with a JWT signed with `jwt_secret` whose `sub` is the
+/// tenant name.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct AuthSettings {
+    /// Reject mutations and listings from unauthenticated callers. Turn off
+    /// only for local development.
+    pub(crate) required: bool,
+    /// HS256 secret for bearer JWTs.
+    pub(crate) jwt_secret: Option<String>,
+    /// Tenants that may read operator data, such as the paying accounts'
+    /// balances.
+    pub(crate) operators: Vec<String>,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        AuthSettings {
+            required: true,
+            jwt_secret: None,
+            operators: vec![],
+        }
+    }
+}
+
+/// Per-tenant overrides. Unset sections use the deployment defaults.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct TenantSettings {
+    /// Hex sha256 of each API key the tenant may use.
+    pub(crate) api_keys: Vec<String>,
+    pub(crate) near: Option<NearSettings>,
+    pub(crate) quota: Option<QuotaSettings>,
+    pub(crate) routing: Option<RoutingSettings>,
+    pub(crate) failover: Option<FailoverSettings>,
+}
+
+/// Limits on `storeBlob`. Top-level limits count blobs on every DA; `da`
+/// adds limits for single DAs, keyed `avail`, `eigenda`, `near` or
+/// `celestia`.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct QuotaSettings {
+    #[serde(flatten)]
+    pub(crate) limits: Limits,
+    pub(crate) da: HashMap<String, Limits>,
+}
+
+/// Unset limits do not apply.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct Limits {
+    pub(crate) blobs_per_minute: Option<u64>,
+    /// Per UTC day.
+    pub(crate) bytes_per_day: Option<u64>,
+    pub(crate) max_blob_size: Option<u64>,
+    /// Submissions still being sent to their DA.
+    pub(crate) max_in_flight: Option<u64>,
+}
+
+/// Retrying a failed submission on other DAs. A submission that errors or
+/// takes longer than `timeout_secs` moves on to the next DA in `fallback`
+/// that it has not been tried on, once what was sent to the first DA is
+/// known not to have landed.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct FailoverSettings {
+    /// DAs to try in order, keyed like `quota.da`.
+    pub(crate) fallback: Vec<String>,
+    pub(crate) timeout_secs: u64,
+}
+
+impl Default for FailoverSettings {
+    fn default() -> Self {
+        FailoverSettings {
+            fallback: vec![],
+            timeout_secs: 300,
+        }
+    }
+}
+
+/// How calls to one backend are retried, and when its circuit breaker
+/// opens. Only calls that cannot have taken effect are retried.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct RetryPolicy {
+    /// Tries per call, the first included.
+    pub(crate) max_attempts: u32,
+    /// Backoff before the first retry; it doubles for each further one.
+    pub(crate) base_delay_ms: u64,
+    pub(crate) max_delay_ms: u64,
+    /// Failures in a row that open the breaker.
+    pub(crate) failure_threshold: u32,
+    /// How long an open breaker refuses calls.
+    pub(crate) cooldown_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 5000,
+            failure_threshold: 5,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+/// Checks made by `/ready`. Every backend is checked unless listed in
+/// `skip`, by the same names as `retry`.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct ReadySettings {
+    /// How long each check may take before its backend counts as down.
+    pub(crate) timeout_secs: u64,
+    /// Oldest acceptable Avail finalized head.
+    pub(crate) max_head_age_secs: u64,
+    pub(crate) skip: Vec<String>,
+}
+
+impl Default for ReadySettings {
+    fn default() -> Self {
+        ReadySettings {
+            timeout_secs: 5,
+            max_head_age_secs: 300,
+            skip: vec![],
+        }
+    }
+}
+
+/// Periodic balance checks of the Avail, NEAR, Celestia and registry
+/// accounts. Thresholds are in whole tokens and keyed by backend name, like
+/// `skip`.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct AccountSettings {
+    pub(crate) interval_secs: u64,
+    pub(crate) min_balance: HashMap<String, f64>,
+    /// URL that gets a JSON POST when an account drops below its threshold.
+    pub(crate) webhook: Option<String>,
+    pub(crate) skip: Vec<String>,
+}
+
+impl Default for AccountSettings {
+    fn default() -> Self {
+        AccountSettings {
+            interval_secs: 300,
+            min_balance: HashMap::new(),
+            webhook: None,
+            skip: vec![],
+        }
+    }
+}
+
+/// How `storeBlob(da: AUTO)` picks a DA. Candidates that break a limit are
+/// ruled out; the rest are ranked by `prefer`.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct RoutingSettings {
+    /// DAs to choose from, in order of preference. Empty means all of them.
+    pub(crate) candidates: Vec<String>,
+    pub(crate) prefer: RoutingPreference,
+    /// Highest estimated cost in USD, registry write included. Needs `prices`.
+    pub(crate) max_cost_usd: Option<f64>,
+    /// Longest expected time until the blob is final.
+    pub(crate) max_latency_secs: Option<u64>,
+    /// Lowest `security` rank accepted.
+    pub(crate) min_security: u8,
+    /// Highest share of failed submissions to a DA in the last
+    /// `health_window_secs` before it is avoided.
+    pub(crate) max_failure_rate: f64,
+    pub(crate) health_window_secs: u64,
+    /// Overrides of each DA's built-in profile, keyed like `quota.da`.
+    pub(crate) da: HashMap<String, DaProfile>,
+}
+
+impl Default for RoutingSettings {
+    fn default() -> Self {
+        RoutingSettings {
+            candidates: vec![],
+            prefer: RoutingPreference::Cheapest,
+            max_cost_usd: None,
+            max_latency_secs: None,
+            min_security: 0,
+            max_failure_rate: 0.5,
+            health_window_secs: 900,
+            da: HashMap::new(),
+        }
+    }
+}
+
+/// How eligible DAs are ranked: by estimated cost, expected latency, recent
+/// failure rate, or `candidates` order.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RoutingPreference {
+    Cheapest,
+    Fastest,
+    Healthiest,
+    Order,
+}
+
+/// What routing assumes about a DA. Unset fields keep the built-in values.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct DaProfile {
+    /// Expected seconds from submission until the blob is final.
+    pub(crate) latency_secs: Option<u64>,
+    pub(crate) max_blob_size: Option<u64>,
+    /// Operator-assigned rank compared against `min_security`.
+    pub(crate) security: Option<u8>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct NearSettings {
+    pub(crate) account_id: String,
+    pub(crate) secret: String,
+    /// Account the blob contract is deployed to.
+    pub(crate) contract: String,
+    pub(crate) namespace_version: u8,
+    pub(crate) namespace_id: u32,
+    pub(crate) network: NearNetwork,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct CelestiaSettings {
+    /// Gas price in utia that submissions pay. Unset, Celestia is not used.
+    pub(crate) gas_price: Option<f64>,
+}
+
+impl Default for NearSettings {
+    fn default() -> Self {
+        NearSettings {
+            account_id: NEAR_ACCOUNT_ID.to_string(),
+            secret: NEAR_SECRET.to_string(),
+            contract: NEAR_ACCOUNT_ID.to_string(),
+            namespace_version: 1,
+            namespace_id: 1,
+            network: NearNetwork::Testnet,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct EigenDaSettings {
+    /// Disperser gRPC endpoint.
+    pub(crate) server: String,
+    /// Hex secp256k1 key of a registered EigenDA account. When set, blobs are
+    /// dispersed through `DisperseBlobAuthenticated` instead of `DisperseBlob`.
+    pub(crate) signing_key: Option<String>,
+    /// Quorums used when `storeBlob` is called without `securityParams`.
+    pub(crate) security_params: Vec<SecurityParam>,
+}
+
+impl Default for EigenDaSettings {
+    fn default() -> Self {
+        EigenDaSettings {
+            server: EIGEN_SERVER.to_string(),
+            signing_key: None,
+            security_params: vec![SecurityParam::default()],
+        }
+    }
+}
+
+/// Where blob pointers are published: `{"kind": "evm", ...}` for a
+/// `MapContract` deployment, `{"kind": "local"}` for the local database only,
+/// or `{"kind": "none"}`.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum RegistrySettings {
+    Evm(EvmRegistrySettings),
+    Local,
+    None,
+}
+
+impl Default for RegistrySettings {
+    fn default() -> Self {
+        RegistrySettings::Evm(EvmRegistrySettings::default())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct EvmRegistrySettings {
+    pub(crate) chain_id: u64,
+    /// Used for writes, and first for reads.
+    pub(crate) rpc: String,
+    /// Further RPCs tried in order when a read through `rpc` fails.
+    pub(crate) read_rpcs: Vec<String>,
+    pub(crate) contract: String,
+    /// Key that owns the contract. There is no default: without it the
+    /// instance is a read-only replica, and blobs can be read but not stored.
+    pub(crate) private_key: Option<String>,
+    /// When set, pointers are queued and published together through
+    /// `saveMany` on a `MapBatch` deployment (see `contracts/MapBatch.sol`).
+    pub(crate) batch: Option<BatchSettings>,
+    /// When set, the server follows the contract's `Blob` events and adds
+    /// pointers published by other instances to the blob index.
+    pub(crate) indexer: Option<IndexerSettings>,
+    pub(crate) gas: GasSettings,
+}
+
+impl Default for EvmRegistrySettings {
+    fn default() -> Self {
+        EvmRegistrySettings {
+            chain_id: 11155420,
+            rpc: OPSEP_RPC.to_string(),
+            read_rpcs: vec![],
+            contract: OPSEP_CONTRACT.to_string(),
+            private_key: None,
+            batch: None,
+            indexer: None,
+            gas: GasSettings::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct BatchSettings {
+    /// Most pointers sent in one transaction.
+    pub(crate) max_size: usize,
+    /// How long the first queued pointer waits for others to join it.
+    pub(crate) max_wait_ms: u64,
+    /// Transactions a pointer may be included in before its save fails.
+    pub(crate) max_attempts: u32,
+}
+
+/// How registry transactions are kept moving. Fees start at the node's
+/// EIP-1559 estimate.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct GasSettings {
+    /// How long to wait for a receipt before replacing the transaction.
+    pub(crate) receipt_timeout_secs: u64,
+    /// Replacements sent before the write is reported as timed out.
+    pub(crate) max_replacements: u32,
+    /// Fee increase for each replacement; most nodes require at least 10.
+    pub(crate) fee_bump_percent: u64,
+}
+
+impl Default for GasSettings {
+    fn default() -> Self {
+        GasSettings {
+            receipt_timeout_secs: 120,
+            max_replacements: 3,
+            fee_bump_percent: 20,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct IndexerSettings {
+    /// Block to scan from when the index has not been synced before,
+    /// normally the contract's deployment block.
+    pub(crate) start_block: u64,
+    /// Most blocks requested in one `eth_getLogs` call.
+    pub(crate) max_range: u64,
+    /// How often to check for new blocks once caught up.
+    pub(crate) poll_ms: u64,
+}
+
+impl Default for IndexerSettings {
+    fn default() -> Self {
+        IndexerSettings {
+            start_block: 0,
+            max_range: 2000,
+            poll_ms: 5000,
+        }
+    }
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        BatchSettings {
+            max_size: 16,
+            max_wait_ms: 2000,
+            max_attempts: 3,
+        }
+    }
+}
+
+/// `"testnet"`, `"mainnet"` or `{"custom": "<rpc url>"}`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum NearNetwork {
+    Testnet,
+    Mainnet,
+    Custom(String),
+}
+
+impl Settings {
+    pub(crate) fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = env::var("DA_CONFIG").unwrap_or_else(|_| "config.json".to_string());
+        let settings: Settings = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(_) if env::var("DA_CONFIG").is_err() => Settings::default(),
+            Err(e) => return Err(format!("Could not read config {}: {}", path, e).into()),
+        };
+        eigen::validate(&settings.eigenda.security_params)
+            .map_err(|e| format!("Invalid eigenda.security_params: {}", e))?;
+        if let Some(key) = &settings.eigenda.signing_key {
+            key.parse::<LocalWallet>()
+                .map_err(|e| format!("Invalid eigenda.signing_key: {}", e))?;
+        }
+        if let Some(price) = settings
+            .celestia
+            .gas_price
+            .filter(|price| !(price.is_finite() && *price > 0.0))
+        {
+            return Err(format!("Invalid celestia.gas_price {}", price).into());
+        }
+        if settings.celestia.gas_price.is_none() {
+            eprintln!("celestia.gas_price is not set; Celestia submissions are disabled");
+        }
+        for (tenant, near) in std::iter::once((None, &settings.near)).chain(
+            settings
+                .tenants
+                .iter()
+                .filter_map(|(name, t)| Some((Some(name), t.near.as_ref()?))),
+        ) {
+            near::validate(near).map_err(|e| match tenant {
+                Some(name) => format!("Invalid tenants.{}.near: {}", name, e),
+                None => format!("Invalid near: {}", e),
+            })?;
+        }
+        if let Some(name) = settings
+            .auth
+            .operators
+            .iter()
+            .find(|name| !settings.tenants.contains_key(*name))
+        {
+            return Err(format!("Unknown tenant {} in auth.operators", name).into());
+        }
+        if let RegistrySettings::Evm(evm) = &settings.registry {
+            match &evm.private_key {
+                Some(key) => {
+                    key.parse::<LocalWallet>()
+                        .map_err(|e| format!("Invalid registry.private_key: {}", e))?;
+                }
+                None => eprintln!("registry.private_key is not set; storeBlob is disabled"),
+            }
+        }
+        for quota in std::iter::once(&settings.quota)
+            .chain(settings.tenants.values().filter_map(|t| t.quota.as_ref()))
+        {
+            if let Some(key) = quota.da.keys().find(|key| DA::from_name(key).is_none()) {
+                return Err(format!("Unknown DA {} in quota", key).into());
+            }
+        }
+        for routing in std::iter::once(&settings.routing)
+            .chain(settings.tenants.values().filter_map(|t| t.routing.as_ref()))
+        {
+            if let Some(key) = routing
+                .candidates
+                .iter()
+                .chain(routing.da.keys())
+                .find(|key| DA::from_name(key).is_none())
+            {
+                return Err(format!("Unknown DA {} in routing", key).into());
+            }
+            if routing.max_cost_usd.is_some() && settings.prices.is_empty() {
+                return Err("routing.max_cost_usd needs prices".into());
+            }
+        }
+        for failover in std::iter::once(&settings.failover).chain(
+            settings
+                .tenants
+                .values()
+                .filter_map(|t| t.failover.as_ref()),
+        ) {
+            if let Some(key) = failover
+                .fallback
+                .iter()
+                .find(|key| DA::from_name(key).is_none())
+            {
+                return Err(format!("Unknown DA {} in failover", key).into());
+            }
+        }
+        let accounts = &settings.accounts;
+        for (section, keys) in [
+            ("ready.skip", settings.ready.skip.iter().collect::<Vec<_>>()),
+            ("accounts.skip", accounts.skip.iter().collect()),
+            (
+                "accounts.min_balance",
+                accounts.min_balance.keys().collect(),
+            ),
+        ] {
+            if let Some(key) = keys
+                .into_iter()
+                .find(|key| !Backend::ALL.iter().any(|b| b.name() == key.as_str()))
+            {
+                return Err(format!("Unknown backend {} in {}", key, section).into());
+            }
+        }
+        if accounts.interval_secs == 0 {
+            return Err("accounts.interval_secs must be positive".into());
+        }
+        for (key, policy) in &settings.retry {
+            if !Backend::ALL
+                .iter()
+                .any(|backend| backend.name() == key.as_str())
+            {
+                return Err(format!("Unknown backend {} in retry", key).into());
+            }
+            if policy.max_attempts == 0 || policy.failure_threshold == 0 {
+                return Err(format!(
+                    "retry.{}.max_attempts and failure_threshold must be positive",
+                    key
+                )
+                .into());
+            }
+        }
+        if let RegistrySettings::Evm(EvmRegistrySettings {
+            batch: Some(batch), ..
+        }) = &settings.registry
+        {
+            if batch.max_size == 0 || batch.max_attempts == 0 {
+                return Err("registry.batch.max_size and max_attempts must be positive".into());
+            }
+        }
+        Ok(settings)
+    }
+
+    pub(crate) fn tenant(&self, tenant: Option<&str>) -> Option<&TenantSettings> {
+        tenant.and_then(|t| self.tenants.get(t))
+    }
+
+    pub(crate) fn quota(&self, tenant: Option<&str>) -> &QuotaSettings {
+        self.tenant(tenant)
+            .and_then(|t| t.quota.as_ref())
+            .unwrap_or(&self.quota)
+    }
+
+    pub(crate) fn routing(&self, tenant: Option<&str>) -> &RoutingSettings {
+        self.tenant(tenant)
+            .and_then(|t| t.routing.as_ref())
+            .unwrap_or(&self.routing)
+    }
+
+    pub(crate) fn failover(&self, tenant: Option<&str>) -> &FailoverSettings {
+        self.tenant(tenant)
+            .and_then(|t| t.failover.as_ref())
+            .unwrap_or(&self.failover)
+    }
+
+    pub(crate) fn near(&self, tenant: Option<&str>) -> &NearSettings {
+        self.tenant(tenant)
+            .and_then(|t| t.near.as_ref())
+            .unwrap_or(&self.near)
+    }
+}
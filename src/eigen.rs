@@ -0,0 +1,486 @@
+use async_graphql::{InputObject, SimpleObject};
+use ethers::{
+    core::k256::elliptic_curve::sec1::ToEncodedPoint,
+    signers::LocalWallet,
+    types::H256,
+    utils::{hex::encode_prefixed, keccak256},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Channel, Code, Status};
+
+use crate::{
+    config::EigenDaSettings,
+    disperser::{
+        authenticated_reply, authenticated_request, disperser_client::DisperserClient,
+        AuthenticatedRequest, AuthenticationData, BlobInfo, BlobStatusReply, BlobStatusRequest,
+        DisperseBlobReply, DisperseBlobRequest, RetrieveBlobRequest, SecurityParams,
+    },
+    model::DA,
+    retry::Failure,
+};
+
+/// Security parameters for one quorum, as accepted by `DisperseBlobRequest`.
+#[derive(SimpleObject, InputObject, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[graphql(input_name = "SecurityParamInput")]
+pub(crate) struct SecurityParam {
+    pub(crate) quorum_id: u32,
+    pub(crate) adversary_threshold: u32,
+    pub(crate) quorum_threshold: u32,
+}
+
+impl Default for SecurityParam {
+    fn default() -> Self {
+        SecurityParam {
+            quorum_id: 0,
+            adversary_threshold: 25,
+            quorum_threshold: 50,
+        }
+    }
+}
+
+impl From<SecurityParam> for SecurityParams {
+    fn from(param: SecurityParam) -> Self {
+        SecurityParams {
+            quorum_id: param.quorum_id,
+            adversary_threshold: param.adversary_threshold,
+            quorum_threshold: param.quorum_threshold,
+        }
+    }
+}
+
+/// Checks `params` against the rules documented on `SecurityParams` in
+/// disperser.proto, so bad requests are rejected before reaching the disperser.
+pub(crate) fn validate(params: &[SecurityParam]) -> Result<(), String> {
+    if params.is_empty() {
+        return Err("At least one quorum is required".to_string());
+    }
+    for (i, param) in params.iter().enumerate() {
+        if param.quorum_id > 255 {
+            return Err(format!(
+                "Quorum {}: id must be in [0, 255]",
+                param.quorum_id
+            ));
+        }
+        if params[..i].iter().any(|p| p.quorum_id == param.quorum_id) {
+            return Err(format!(
+                "Quorum {} is listed more than once",
+                param.quorum_id
+            ));
+        }
+        if !(1..100).contains(&param.adversary_threshold) {
+            return Err(format!(
+                "Quorum {}: adversary threshold must be in [1, 100)",
+                param.quorum_id
+            ));
+        }
+        if !(1..=100).contains(&param.quorum_threshold) {
+            return Err(format!(
+                "Quorum {}: quorum threshold must be in [1, 100]",
+                param.quorum_id
+            ));
+        }
+        if param.quorum_threshold <= param.adversary_threshold + 10 {
+            return Err(format!(
+                "Quorum {}: quorum threshold must exceed adversary threshold + 10",
+                param.quorum_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Everything EigenDA returns for a confirmed blob, kept so a rollup contract
+/// can verify it against EigenDAServiceManager. Mirrors `BlobInfo` in
+/// disperser.proto; byte fields are 0x-prefixed hex.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EigenBlobInfo {
+    pub(crate) blob_header: EigenBlobHeader,
+    pub(crate) blob_verification_proof: EigenBlobVerificationProof,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EigenBlobHeader {
+    pub(crate) commitment: String,
+    pub(crate) data_length: u32,
+    pub(crate) blob_quorum_params: Vec<EigenBlobQuorumParam>,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EigenBlobQuorumParam {
+    pub(crate) quorum_number: u32,
+    pub(crate) adversary_threshold_percentage: u32,
+    pub(crate) quorum_threshold_percentage: u32,
+    pub(crate) quantization_param: u32,
+    pub(crate) encoded_length: u64,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EigenBlobVerificationProof {
+    pub(crate) batch_id: u32,
+    pub(crate) blob_index: u32,
+    pub(crate) batch_metadata: EigenBatchMetadata,
+    pub(crate) inclusion_proof: String,
+    pub(crate) quorum_indexes: String,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EigenBatchMetadata {
+    pub(crate) batch_header: EigenBatchHeader,
+    pub(crate) signatory_record_hash: String,
+    pub(crate) fee: String,
+    pub(crate) confirmation_block_number: u32,
+    pub(crate) batch_header_hash: String,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EigenBatchHeader {
+    pub(crate) batch_root: String,
+    pub(crate) quorum_numbers: String,
+    pub(crate) quorum_signed_percentages: String,
+    pub(crate) reference_block_number: u32,
+}
+
+impl From<BlobInfo> for EigenBlobInfo {
+    fn from(info: BlobInfo) -> Self {
+        let header = info.blob_header.unwrap_or_default();
+        let proof = info.blob_verification_proof.unwrap_or_default();
+        let metadata = proof.batch_metadata.unwrap_or_default();
+        let batch_header = metadata.batch_header.unwrap_or_default();
+
+        EigenBlobInfo {
+            blob_header: EigenBlobHeader {
+                commitment: encode_prefixed(header.commitment),
+                data_length: header.data_length,
+                blob_quorum_params: header
+                    .blob_quorum_params
+                    .into_iter()
+                    .map(|p| EigenBlobQuorumParam {
+                        quorum_number: p.quorum_number,
+                        adversary_threshold_percentage: p.adversary_threshold_percentage,
+                        quorum_threshold_percentage: p.quorum_threshold_percentage,
+                        quantization_param: p.quantization_param,
+                        encoded_length: p.encoded_length,
+                    })
+                    .collect(),
+            },
+            blob_verification_proof: EigenBlobVerificationProof {
+                batch_id: proof.batch_id,
+                blob_index: proof.blob_index,
+                batch_metadata: EigenBatchMetadata {
+                    batch_header: EigenBatchHeader {
+                        batch_root: encode_prefixed(batch_header.batch_root),
+                        quorum_numbers: encode_prefixed(batch_header.quorum_numbers),
+                        quorum_signed_percentages: encode_prefixed(
+                            batch_header.quorum_signed_percentages,
+                        ),
+                        reference_block_number: batch_header.reference_block_number,
+                    },
+                    signatory_record_hash: encode_prefixed(metadata.signatory_record_hash),
+                    fee: encode_prefixed(metadata.fee),
+                    confirmation_block_number: metadata.confirmation_block_number,
+                    batch_header_hash: encode_prefixed(metadata.batch_header_hash),
+                },
+                inclusion_proof: encode_prefixed(proof.inclusion_proof),
+                quorum_indexes: encode_prefixed(proof.quorum_indexes),
+            },
+        }
+    }
+}
+
+/// Disperses `request` over the authenticated `DisperseBlobAuthenticated`
+/// stream: send the request tagged with our account id, sign the challenge the
+/// disperser sends back, then wait for the reply.
+pub(crate) async fn disperse_authenticated(
+    client: &mut DisperserClient<Channel>,
+    wallet: &LocalWallet,
+    request: DisperseBlobRequest,
+) -> Result<DisperseBlobReply, Status> {
+    let (tx, rx) = mpsc::channel(2);
+    let request = DisperseBlobRequest {
+        account_id: account_id(wallet),
+        ..request
+    };
+    send(
+        &tx,
+        authenticated_request::Payload::DisperseRequest(request),
+    )
+    .await?;
+
+    let mut replies = client
+        .disperse_blob_authenticated(ReceiverStream::new(rx))
+        .await?
+        .into_inner();
+
+    let challenge = match replies.message().await?.and_then(|r| r.payload) {
+        Some(authenticated_reply::Payload::BlobAuthHeader(header)) => header.challenge_parameter,
+        _ => {
+            return Err(Status::internal(
+                "Disperser did not send a blob auth header",
+            ))
+        }
+    };
+    let authentication_data = sign_challenge(wallet, challenge)?;
+    send(
+        &tx,
+        authenticated_request::Payload::AuthenticationData(AuthenticationData {
+            authentication_data,
+        }),
+    )
+    .await?;
+
+    match replies.message().await?.and_then(|r| r.payload) {
+        Some(authenticated_reply::Payload::DisperseReply(reply)) => Ok(reply),
+        _ => Err(Status::internal("Disperser did not send a disperse reply")),
+    }
+}
+
+async fn send(
+    tx: &mpsc::Sender<AuthenticatedRequest>,
+    payload: authenticated_request::Payload,
+) -> Result<(), Status> {
+    tx.send(AuthenticatedRequest {
+        payload: Some(payload),
+    })
+    .await
+    .map_err(|_| Status::internal("Authenticated dispersal stream closed"))
+}
+
+/// The disperser identifies accounts by their uncompressed secp256k1 public key.
+fn account_id(wallet: &LocalWallet) -> String {
+    encode_prefixed(
+        wallet
+            .signer()
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes(),
+    )
+}
+
+/// Signs keccak256 of the big-endian challenge, in go-ethereum's `r || s || v`
+/// form where `v` is the raw recovery id.
+fn sign_challenge(wallet: &LocalWallet, challenge: u32) -> Result<Vec<u8>, Status> {
+    let hash = keccak256(challenge.to_be_bytes());
+    let mut signature = wallet
+        .sign_hash(H256(hash))
+        .map_err(|e| Status::internal(format!("Failed to sign auth header: {}", e)))?;
+    signature.v -= 27;
+    Ok(signature.to_vec())
+}
+
+/// Codec tag appended to EigenDA registry pointers for blobs encoded with
+/// [`encode`]. Pointers without it hold raw bytes.
+pub(crate) const CODEC_FIELD_ELEMENTS: u8 = 1;
+
+/// Bytes of payload carried by each 32-byte field element.
+const SYMBOL_PAYLOAD: usize = 31;
+
+/// Makes arbitrary bytes a valid EigenDA blob: the payload is prefixed with its
+/// big-endian u32 length, split into 31-byte chunks and each chunk is written
+/// behind a zero byte, so every 32-byte symbol is below the BN254 modulus.
+pub(crate) fn encode(data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(4 + data.len());
+    payload.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    payload.extend_from_slice(data);
+
+    let mut encoded = Vec::with_capacity((payload.len() / SYMBOL_PAYLOAD + 1) * 32);
+    for chunk in payload.chunks(SYMBOL_PAYLOAD) {
+        encoded.push(0);
+        encoded.extend_from_slice(chunk);
+        encoded.resize(encoded.len() + SYMBOL_PAYLOAD - chunk.len(), 0);
+    }
+    encoded
+}
+
+/// Inverse of [`encode`]. Tolerates the zero padding the disperser adds to
+/// round blobs up to a power of two symbols.
+pub(crate) fn decode(blob: &[u8]) -> Result<Vec<u8>, String> {
+    let mut payload = Vec::with_capacity(blob.len());
+    for symbol in blob.chunks(32) {
+        if symbol[0] != 0 {
+            return Err("Blob is not field element encoded".to_string());
+        }
+        payload.extend_from_slice(&symbol[1..]);
+    }
+
+    let len = payload
+        .get(..4)
+        .map(|len| u32::from_be_bytes(len.try_into().unwrap()) as usize)
+        .ok_or("Blob is too short to hold a length prefix")?;
+    payload
+        .get(4..4 + len)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| format!("Blob is shorter than its {} byte length prefix", len))
+}
+
+pub(crate) async fn client(server: &str) -> Result<DisperserClient<Channel>, Failure> {
+    DisperserClient::connect(server.to_string())
+        .await
+        .map_err(|e| Failure::Transient(format!("Failed to connect to external service: {}", e)))
+}
+
+/// A dispersal the disperser turned away, for rate limiting or while
+/// unavailable, was not taken and can be sent again.
+fn dispersal_failure(status: Status) -> Failure {
+    let message = format!("Failed to send request to external service: {}", status);
+    match status.code() {
+        Code::ResourceExhausted | Code::Unavailable => Failure::Transient(message),
+        Code::InvalidArgument => Failure::Invalid(message),
+        _ => Failure::Final(message),
+    }
+}
+
+/// Encodes and disperses `data` with `params`, authenticating when a signing
+/// key is configured. Returns the disperser's request id.
+pub(crate) async fn disperse(
+    settings: &EigenDaSettings,
+    data: &[u8],
+    params: &[SecurityParam],
+) -> Result<Vec<u8>, Failure> {
+    let request = DisperseBlobRequest {
+        data: encode(data),
+        security_params: params.iter().map(|&p| p.into()).collect(),
+        ..Default::default()
+    };
+
+    let mut client = client(&settings.server).await?;
+    let response = match &settings.signing_key {
+        Some(key) => {
+            let wallet = key
+                .parse::<LocalWallet>()
+                .map_err(|e| Failure::Invalid(format!("Invalid eigenda.signing_key: {}", e)))?;
+            disperse_authenticated(&mut client, &wallet, request).await
+        }
+        None => client.disperse_blob(request).await.map(|r| r.into_inner()),
+    }
+    .map_err(dispersal_failure)?;
+
+    Ok(response.request_id)
+}
+
+pub(crate) async fn status(server: &str, request_id: Vec<u8>) -> Result<BlobStatusReply, Failure> {
+    client(server)
+        .await?
+        .get_blob_status(BlobStatusRequest { request_id })
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| {
+            Failure::Transient(format!("Failed to send request to external service: {}", e))
+        })
+}
+
+/// Registry pointer for a confirmed blob:
+/// `DA::EigenDA | batch_header_hash[32] | blob_index[4 LE] | (codec[1])`.
+pub(crate) fn pointer(info: &BlobInfo, codec: Option<u8>) -> Vec<u8> {
+    let proof = info.blob_verification_proof.clone().unwrap_or_default();
+    let mut ptr = vec![DA::EigenDA as u8];
+    ptr.extend_from_slice(&proof.batch_metadata.unwrap_or_default().batch_header_hash);
+    ptr.extend_from_slice(&proof.blob_index.to_le_bytes());
+    ptr.extend(codec);
+    ptr
+}
+
+pub(crate) async fn retrieve(server: &str, ptr: &[u8]) -> Result<Vec<u8>, Failure> {
+    if ptr.len() < 37 {
+        return Err(Failure::Invalid("Malformed EigenDA pointer".to_string()));
+    }
+    let request = RetrieveBlobRequest {
+        batch_header_hash: ptr[1..33].to_vec(),
+        blob_index: u32::from_le_bytes(ptr[33..37].try_into().unwrap()),
+    };
+
+    let data = client(server)
+        .await?
+        .retrieve_blob(request)
+        .await
+        .map_err(|e| {
+            Failure::Transient(format!("Failed to send request to external service: {}", e))
+        })?
+        .into_inner()
+        .data;
+
+    match ptr.get(37).copied() {
+        Some(CODEC_FIELD_ELEMENTS) => decode(&data).map_err(Failure::Invalid),
+        _ => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic bytes that are not all alike, so misplaced chunks show.
+    fn bytes(len: usize) -> Vec<u8> {
+        let mut state = len as u32 ^ 0x9e37_79b9;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_across_symbol_boundaries() {
+        // 27 and 58 bytes fill the symbols exactly with the 4-byte prefix.
+        let lengths = (0..=100).chain([124, 155, 1000, 4096]);
+        for len in lengths {
+            let data = bytes(len);
+            let encoded = encode(&data);
+            assert_eq!(encoded.len() % 32, 0, "length {}", len);
+            assert!(encoded.chunks(32).all(|symbol| symbol[0] == 0));
+            assert_eq!(decode(&encoded).unwrap(), data, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        let encoded = encode(&[]);
+        assert_eq!(encoded.len(), 32);
+        assert_eq!(decode(&encoded).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn round_trips_31_and_32_bytes() {
+        for len in [31, 32] {
+            let data = vec![0xff; len];
+            let encoded = encode(&data);
+            assert_eq!(encoded.len(), 64);
+            assert_eq!(decode(&encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn decodes_with_disperser_padding() {
+        // 104 bytes of payload take 4 symbols; pad to 8 as the disperser would
+        // for a larger blob.
+        let data = bytes(100);
+        let mut blob = encode(&data);
+        assert_eq!(blob.len(), 4 * 32);
+        blob.resize(8 * 32, 0);
+        assert_eq!(decode(&blob).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_symbols_with_a_nonzero_first_byte() {
+        let mut blob = encode(&bytes(100));
+        blob[64] = 1;
+        assert!(decode(&blob).is_err());
+    }
+
+    #[test]
+    fn rejects_length_prefix_past_the_payload() {
+        let mut blob = encode(&bytes(40));
+        blob[1..5].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(decode(&blob).is_err());
+    }
+
+    #[test]
+    fn rejects_blobs_too_short_for_a_prefix() {
+        assert!(decode(&[0, 0, 0]).is_err());
+        assert!(decode(&[]).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use async_graphql::{Enum, SimpleObject};
+
+use crate::{
+    avail, billing, celestia,
+    model::DA,
+    near::{self, NearPointer},
+    ApiContext,
+};
+
+/// Largest blob `estimateCost` prices. Avail is only dry-run up to its own
+/// limit, `avail::MAX_BLOB_SIZE`.
+pub(crate) const MAX_ESTIMATE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How far an estimate can be off. Variants are ordered from most to least
+/// certain.
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub(crate) enum Confidence {
+    /// The amount that would be charged, barring fee market moves.
+    High,
+    /// A model of the chain's fee schedule at current prices.
+    Medium,
+    /// Some fees could not be estimated.
+    Low,
+}
+
+/// One fee a submission would pay.
+#[derive(SimpleObject, Clone, Debug)]
+pub(crate) struct FeeEstimate {
+    /// `da` for the DA submission, `registry` for publishing the pointer.
+    pub(crate) kind: String,
+    /// Decimal integer in `unit`.
+    pub(crate) amount: String,
+    pub(crate) unit: String,
+    /// `amount` in whole `token`s.
+    pub(crate) value: f64,
+    pub(crate) token: String,
+    /// At the configured `prices`; `null` when `token` has no price.
+    pub(crate) usd: Option<f64>,
+    pub(crate) confidence: Confidence,
+    /// How the amount was obtained.
+    pub(crate) source: String,
+}
+
+/// What storing a blob on one DA would cost right now.
+#[derive(SimpleObject, Clone, Debug)]
+pub(crate) struct CostEstimate {
+    pub(crate) da: DA,
+    pub(crate) fees: Vec<FeeEstimate>,
+    /// Sum of `fees` in USD; `null` unless every fee was estimated and priced.
+    pub(crate) total_usd: Option<f64>,
+    /// The least certain of `fees`, or `LOW` when one is missing.
+    pub(crate) confidence: Confidence,
+    /// Why fees could not be estimated.
+    pub(crate) errors: Vec<String>,
+}
+
+/// Estimates for each of `das`, cheapest first; DAs without a USD total come
+/// last. Backends are asked concurrently.
+pub(crate) async fn compare(
+    api_context: &ApiContext,
+    tenant: Option<&str>,
+    das: &[DA],
+    size: u64,
+) -> Vec<CostEstimate> {
+    let tasks: Vec<_> = das
+        .iter()
+        .map(|&da| {
+            let api_context = api_context.clone();
+            let tenant = tenant.map(str::to_string);
+            tokio::spawn(async move { estimate(&api_context, tenant.as_deref(), da, size).await })
+        })
+        .collect();
+
+    let mut estimates = vec![];
+    for task in tasks {
+        match task.await {
+            Ok(estimate) => estimates.push(estimate),
+            Err(e) => eprintln!("Cost estimate failed: {}", e),
+        }
+    }
+    estimates.sort_by(|a, b| match (a.total_usd, b.total_usd) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    estimates
+}
+
+/// Fees for storing `size` bytes on `da` and publishing its pointer.
+pub(crate) async fn estimate(
+    api_context: &ApiContext,
+    tenant: Option<&str>,
+    da: DA,
+    size: u64,
+) -> CostEstimate {
+    let prices = &api_context.settings.prices;
+    let fee = |kind: &str, amount: u128, unit: &str, confidence: Confidence, source: &str| {
+        let (token, decimals) = billing::token(unit).unwrap_or((unit, 0));
+        let value = amount as f64 / 10f64.powi(decimals);
+        FeeEstimate {
+            kind: kind.to_string(),
+            amount: amount.to_string(),
+            unit: unit.to_string(),
+            value,
+            token: token.to_string(),
+            usd: prices.get(token).map(|price| value * price),
+            confidence,
+            source: source.to_string(),
+        }
+    };
+
+    let mut fees = vec![];
+    let mut errors = vec![];
+    let near_settings = api_context.settings.near(tenant);
+    let ptr_len = match da {
+        DA::Celestia => {
+            match api_context.settings.celestia.gas_price {
+                Some(price) => fees.push(fee(
+                    "da",
+                    celestia::fee(celestia::gas_limit(size), price).into(),
+                    "utia",
+                    Confidence::High,
+                    "celestia-app PayForBlobs gas at celestia.gas_price",
+                )),
+                None => errors.push("celestia.gas_price is not set".to_string()),
+            }
+            // Height and commitment.
+            41
+        }
+        DA::Avail => {
+            match avail::estimate(size as usize).await {
+                Ok(amount) => fees.push(fee(
+                    "da",
+                    amount,
+                    "plank",
+                    Confidence::High,
+                    "payment_queryInfo",
+                )),
+                Err(e) => errors.push(e),
+            }
+            // Block hash and extrinsic index.
+            37
+        }
+        DA::Near => {
+            match near::estimate(&near_settings.network, size).await {
+                Ok(amount) => fees.push(fee(
+                    "da",
+                    amount,
+                    "yoctoNEAR",
+                    Confidence::Medium,
+                    "NEAR fee schedule at the current gas price",
+                )),
+                Err(e) => errors.push(e),
+            }
+            NearPointer {
+                tx_hash: [0; 32],
+                namespace_version: near_settings.namespace_version,
+                namespace_id: near_settings.namespace_id,
+                network: near_settings.network.clone(),
+                contract: near_settings.contract.clone(),
+                signer: near_settings.account_id.clone(),
+            }
+            .encode()
+            .len()
+        }
+        DA::EigenDA => {
+            fees.push(fee(
+                "da",
+                0,
+                "wei",
+                Confidence::High,
+                "the EigenDA disperser does not charge",
+            ));
+            // Batch header hash, blob index and codec byte.
+            38
+        }
+    };
+
+    match api_context.registry.estimate_save(ptr_len).await {
+        Ok(Some(amount)) => fees.push(fee(
+            "registry",
+            amount,
+            "wei",
+            Confidence::Medium,
+            "eth_estimateGas at the current gas price, plus the L1 data fee",
+        )),
+        Ok(None) => {}
+        Err(e) => errors.push(e),
+    }
+
+    let total_usd: Option<f64> = if errors.is_empty() {
+        fees.iter().map(|fee| fee.usd).sum()
+    } else {
+        None
+    };
+    let confidence = if errors.is_empty() {
+        fees.iter()
+            .map(|fee| fee.confidence)
+            .max()
+            .unwrap_or(Confidence::High)
+    } else {
+        Confidence::Low
+    };
+    CostEstimate {
+        da,
+        fees,
+        total_usd,
+        confidence,
+        errors,
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension};
+
+use crate::{
+    billing::{self, Fee},
+    Data,
+};
+
+/// Columns kept next to each record so listings can filter and page in SQL.
+/// Bump `SCHEMA_VERSION` with each migration added to `open`.
+const SCHEMA_VERSION: i64 = 3;
+
+/// Filter for `Index::list`; unset fields match everything. `from` and `to`
+/// bound the creation time, in unix seconds, inclusively.
+#[derive(Default)]
+pub(crate) struct BlobQuery {
+    pub(crate) da: Option<u8>,
+    pub(crate) status: Option<String>,
+    pub(crate) tenant: Option<String>,
+    pub(crate) from: Option<u64>,
+    pub(crate) to: Option<u64>,
+    /// Metadata entries a blob must all have.
+    pub(crate) tags: Vec<(String, String)>,
+}
+
+/// Blob counts, bytes and fees for one tenant, DA and UTC day.
+pub(crate) struct DailyTotals {
+    /// Days since the unix epoch.
+    pub(crate) day: u64,
+    pub(crate) tenant: Option<String>,
+    pub(crate) da: u8,
+    pub(crate) blobs: u64,
+    pub(crate) bytes: u64,
+    /// Summed amounts by fee kind and unit.
+    pub(crate) fees: BTreeMap<(String, String), u128>,
+}
+
+/// SQLite-backed copy of the blob index so records (EigenDA request ids,
+/// verification proofs, registry indexes) survive a restart. The in-memory
+/// `SharedMap` stays the read path; every write goes through here first.
+pub(crate) struct Index {
+    conn: Mutex<Connection>,
+}
+
+impl Index {
+    pub(crate) fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS blobs (
+                id BLOB PRIMARY KEY,
+                record TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS pointers (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                pointer BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS blob_tags (
+                id BLOB NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (id, key)
+            );
+            CREATE INDEX IF NOT EXISTS blob_tags_key_value ON blob_tags (key, value);
+            CREATE TABLE IF NOT EXISTS blob_fees (
+                id BLOB NOT NULL,
+                kind TEXT NOT NULL,
+                unit TEXT NOT NULL,
+                amount TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS blob_fees_id ON blob_fees (id);
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            );",
+        )?;
+
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < 1 {
+            conn.execute_batch(
+                "ALTER TABLE blobs ADD COLUMN da INTEGER;
+                ALTER TABLE blobs ADD COLUMN status TEXT;
+                ALTER TABLE blobs ADD COLUMN tenant TEXT;
+                ALTER TABLE blobs ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE blobs ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
+                CREATE INDEX blobs_created_at ON blobs (created_at, id);",
+            )?;
+        }
+        if version < 2 {
+            conn.execute_batch(
+                "ALTER TABLE blobs ADD COLUMN size INTEGER NOT NULL DEFAULT 0;
+                CREATE INDEX blobs_tenant_created_at ON blobs (tenant, created_at);",
+            )?;
+        }
+
+        let index = Index {
+            conn: Mutex::new(conn),
+        };
+        if version < SCHEMA_VERSION {
+            // Rewrite existing records so the new columns are filled in. From
+            // version 3 statuses are upper case; earlier versions wrote
+            // `Processing` for blobs still being submitted.
+            for (id, mut data) in index.load()? {
+                let obj = data.obj_mut();
+                obj.status = obj.status.to_uppercase();
+                for change in &mut obj.history {
+                    change.status = change.status.to_uppercase();
+                }
+                index.put(&id, &data)?;
+            }
+            index
+                .conn
+                .lock()
+                .unwrap()
+                .execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+        }
+        Ok(index)
+    }
+
+    pub(crate) fn load(&self) -> rusqlite::Result<HashMap<[u8; 32], Data>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT id, record FROM blobs")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, [u8; 32]>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut map = HashMap::new();
+        for row in rows {
+            let (id, record) = row?;
+            match serde_json::from_str(&record) {
+                Ok(data) => {
+                    map.insert(id, data);
+                }
+                Err(e) => eprintln!("Skipping unreadable index record: {}", e),
+            }
+        }
+        Ok(map)
+    }
+
+    pub(crate) fn put(&self, id: &[u8; 32], data: &Data) -> rusqlite::Result<()> {
+        let record = serde_json::to_string(data).expect("index records serialize");
+        let obj = data.obj();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO blobs (id, record, da, status, tenant, size, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO UPDATE SET
+                record = excluded.record,
+                da = excluded.da,
+                status = excluded.status,
+                tenant = excluded.tenant,
+                size = excluded.size,
+                created_at = excluded.created_at,
+                updated_at = excluded.updated_at",
+            params![
+                &id[..],
+                record,
+                data.da() as u8,
+                obj.status,
+                obj.tenant,
+                obj.size as i64,
+                obj.created_at as i64,
+                obj.updated_at as i64
+            ],
+        )?;
+        tx.execute("DELETE FROM blob_tags WHERE id = ?1", params![&id[..]])?;
+        for (key, value) in &obj.metadata {
+            tx.execute(
+                "INSERT INTO blob_tags (id, key, value) VALUES (?1, ?2, ?3)",
+                params![&id[..], key, value],
+            )?;
+        }
+        tx.execute("DELETE FROM blob_fees WHERE id = ?1", params![&id[..]])?;
+        for fee in &obj.fees {
+            tx.execute(
+                "INSERT INTO blob_fees (id, kind, unit, amount) VALUES (?1, ?2, ?3, ?4)",
+                params![&id[..], fee.kind, fee.unit, fee.amount],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Number and total size of the blobs `tenant` stored since `since` (unix
+    /// seconds), on `da` or on any DA.
+    pub(crate) fn usage(
+        &self,
+        tenant: Option<&str>,
+        da: Option<u8>,
+        since: u64,
+    ) -> rusqlite::Result<(u64, u64)> {
+        self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM blobs
+             WHERE tenant IS ?1 AND created_at >= ?2 AND (?3 IS NULL OR da = ?3)",
+            params![tenant, since as i64, da],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )
+    }
+
+    /// Submissions to `da` that finished since `since` (unix seconds), and
+    /// how many of them failed.
+    pub(crate) fn outcomes(&self, da: u8, since: u64) -> rusqlite::Result<(u64, u64)> {
+        self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*), COALESCE(SUM(status = 'FAILED'), 0) FROM blobs
+             WHERE da = ?1 AND updated_at >= ?2
+                AND status IN ('FINALIZED', 'CONFIRMED', 'FAILED')",
+            params![da, since as i64],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )
+    }
+
+    /// Totals by UTC day, tenant and DA for blobs created between `from` and
+    /// `to`, oldest day first. `tenant` limits them to one tenant. Fee amounts
+    /// can exceed SQLite's integers, so they are summed here.
+    pub(crate) fn daily_totals(
+        &self,
+        tenant: Option<&str>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> rusqlite::Result<Vec<DailyTotals>> {
+        const FILTER: &str = "(?1 IS NULL OR b.tenant = ?1)
+             AND (?2 IS NULL OR b.created_at >= ?2)
+             AND (?3 IS NULL OR b.created_at <= ?3)";
+        let bounds = params![tenant, from.map(|t| t as i64), to.map(|t| t as i64)];
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT b.created_at / 86400, b.tenant, b.da, COUNT(*), COALESCE(SUM(b.size), 0)
+             FROM blobs b WHERE {}
+             GROUP BY 1, 2, 3 ORDER BY 1, 2, 3",
+            FILTER
+        ))?;
+        let mut totals = stmt
+            .query_map(bounds, |row| {
+                Ok(DailyTotals {
+                    day: row.get::<_, i64>(0)? as u64,
+                    tenant: row.get(1)?,
+                    da: row.get(2)?,
+                    blobs: row.get::<_, i64>(3)? as u64,
+                    bytes: row.get::<_, i64>(4)? as u64,
+                    fees: BTreeMap::new(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT b.created_at / 86400, b.tenant, b.da, f.kind, f.unit, f.amount
+             FROM blob_fees f JOIN blobs b ON b.id = f.id WHERE {}",
+            FILTER
+        ))?;
+        let rows = stmt.query_map(bounds, |row| {
+            Ok((
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, u8>(2)?,
+                Fee {
+                    kind: row.get(3)?,
+                    unit: row.get(4)?,
+                    amount: row.get(5)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (day, tenant, da, fee) = row?;
+            if let Some(totals) = totals
+                .iter_mut()
+                .find(|t| t.day == day && t.tenant == tenant && t.da == da)
+            {
+                billing::add_fee(&mut totals.fees, &fee);
+            }
+        }
+        Ok(totals)
+    }
+
+    /// Up to `limit` records matching `query`, newest first, starting after
+    /// the `(created_at, id)` position `after`.
+    pub(crate) fn list(
+        &self,
+        query: &BlobQuery,
+        after: Option<(u64, [u8; 32])>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<([u8; 32], Data)>> {
+        let mut clauses = vec![];
+        let mut values: Vec<Value> = vec![];
+        if let Some(da) = query.da {
+            clauses.push("da = ?");
+            values.push(Value::Integer(da.into()));
+        }
+        if let Some(status) = &query.status {
+            clauses.push("status = ?");
+            values.push(Value::Text(status.clone()));
+        }
+        if let Some(tenant) = &query.tenant {
+            clauses.push("tenant = ?");
+            values.push(Value::Text(tenant.clone()));
+        }
+        if let Some(from) = query.from {
+            clauses.push("created_at >= ?");
+            values.push(Value::Integer(from as i64));
+        }
+        if let Some(to) = query.to {
+            clauses.push("created_at <= ?");
+            values.push(Value::Integer(to as i64));
+        }
+        for (key, value) in &query.tags {
+            clauses.push("id IN (SELECT id FROM blob_tags WHERE key = ? AND value = ?)");
+            values.push(Value::Text(key.clone()));
+            values.push(Value::Text(value.clone()));
+        }
+        if let Some((created_at, id)) = after {
+            clauses.push("(created_at < ? OR (created_at = ? AND id < ?))");
+            values.push(Value::Integer(created_at as i64));
+            values.push(Value::Integer(created_at as i64));
+            values.push(Value::Blob(id.to_vec()));
+        }
+        let filter = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+        values.push(Value::Integer(limit as i64));
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, record FROM blobs {} ORDER BY created_at DESC, id DESC LIMIT ?",
+            filter
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok((row.get::<_, [u8; 32]>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut blobs = vec![];
+        for row in rows {
+            let (id, record) = row?;
+            match serde_json::from_str(&record) {
+                Ok(data) => blobs.push((id, data)),
+                Err(e) => eprintln!("Skipping unreadable index record: {}", e),
+            }
+        }
+        Ok(blobs)
+    }
+
+    /// Stores a pointer for the local registry and returns its id.
+    pub(crate) fn save_pointer(&self, ptr: &[u8]) -> rusqlite::Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("INSERT INTO pointers (pointer) VALUES (?1)", params![ptr])?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    pub(crate) fn pointer(&self, id: u64) -> rusqlite::Result<Option<Vec<u8>>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT pointer FROM pointers WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Number of blob records and of pointers saved by the local registry.
+    pub(crate) fn sizes(&self) -> rusqlite::Result<(u64, u64)> {
+        self.conn.lock().unwrap().query_row(
+            "SELECT (SELECT COUNT(*) FROM blobs), (SELECT COUNT(*) FROM pointers)",
+            [],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )
+    }
+
+    /// Last registry block the indexer has fully processed.
+    pub(crate) fn indexed_block(&self) -> rusqlite::Result<Option<u64>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'indexed_block'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+            .map(|block| block.map(|b| b as u64))
+    }
+
+    pub(crate) fn set_indexed_block(&self, block: u64) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO meta (key, value) VALUES ('indexed_block', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![block as i64],
+        )?;
+        Ok(())
+    }
+}
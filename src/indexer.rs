@@ -0,0 +1,117 @@
+use std::{sync::Arc, time::Duration};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::Address,
+};
+
+use crate::{
+    config::{EvmRegistrySettings, IndexerSettings},
+    eigen, metadata, registry, ApiContext, BlobFilter, Data, MapContract, Obj, DA,
+};
+
+/// Rebuilds the blob index from the registry contract's `Blob(id, b)` events
+/// and keeps following new blocks. Every pointer that is not already in the
+/// index is recorded under the public id committed in its entry, or under
+/// its registry index for entries written before ids were committed, so
+/// `getBlobStatus` and `getBlobData` work for it. Progress is
+/// stored in the index, so a restart resumes where it stopped.
+pub(crate) async fn run(
+    api_context: ApiContext,
+    registry: EvmRegistrySettings,
+    settings: IndexerSettings,
+) -> Result<(), String> {
+    let provider = Provider::<Http>::try_from(registry.rpc.as_str())
+        .map_err(|e| format!("Invalid registry RPC url: {}", e))?;
+    let address = registry
+        .contract
+        .parse::<Address>()
+        .map_err(|e| format!("Invalid registry contract address: {}", e))?;
+    let contract = MapContract::new(address, Arc::new(provider.clone()));
+
+    let mut next = api_context
+        .index
+        .indexed_block()
+        .map_err(|e| format!("Could not read indexer progress: {}", e))?
+        .map_or(settings.start_block, |block| block + 1);
+    let poll = Duration::from_millis(settings.poll_ms);
+
+    loop {
+        let head = match provider.get_block_number().await {
+            Ok(head) => head.as_u64(),
+            Err(e) => {
+                eprintln!("Indexer could not get the latest block: {}", e);
+                tokio::time::sleep(poll).await;
+                continue;
+            }
+        };
+        if next > head {
+            tokio::time::sleep(poll).await;
+            continue;
+        }
+
+        let to = head.min(next + settings.max_range.max(1) - 1);
+        let events = match contract
+            .event::<BlobFilter>()
+            .from_block(next)
+            .to_block(to)
+            .query()
+            .await
+        {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Indexer could not read blocks {}..={}: {}", next, to, e);
+                tokio::time::sleep(poll).await;
+                continue;
+            }
+        };
+
+        for event in events {
+            record(&api_context, registry::index_of(&event), event.b.to_vec()).await?;
+        }
+        api_context
+            .index
+            .set_indexed_block(to)
+            .map_err(|e| format!("Could not save indexer progress: {}", e))?;
+        next = to + 1;
+    }
+}
+
+/// Adds the pointer saved at registry index `op_index` to the blob index,
+/// unless a record for it already exists.
+async fn record(
+    api_context: &ApiContext,
+    op_index: [u8; 32],
+    entry: Vec<u8>,
+) -> Result<(), String> {
+    let entry = metadata::unwrap(&entry);
+    let id = entry.id.unwrap_or(op_index);
+    let known = api_context.ops.read().await.contains_key(&op_index)
+        || api_context.map.read().await.contains_key(&id);
+    if known {
+        return Ok(());
+    }
+
+    let ptr = entry.pointer;
+    let Some(da) = ptr.first().copied().and_then(DA::from_tag) else {
+        eprintln!("Indexer skipping unknown pointer at {:?}", op_index);
+        return Ok(());
+    };
+    let codec = match da {
+        DA::EigenDA => ptr
+            .get(37)
+            .copied()
+            .filter(|&c| c == eigen::CODEC_FIELD_ELEMENTS),
+        _ => None,
+    };
+
+    let obj = Obj {
+        status: "FINALIZED".to_string(),
+        op_index: Some(op_index),
+        codec,
+        metadata_hash: entry.metadata_hash,
+        pointer: ptr.to_vec(),
+        ..Default::default()
+    };
+    api_context.insert(id, Data::new(da, obj)).await
+}
@@ -1,23 +1,80 @@
+mod accounts;
+mod auth;
+mod avail;
+mod billing;
+mod celestia;
+mod config;
+mod eigen;
+mod estimate;
+mod index;
+mod indexer;
+mod metadata;
+mod metrics;
 mod model;
+mod near;
+mod near_proof;
+mod quota;
+mod readiness;
+mod registry;
+mod retry;
 mod routes;
+mod routing;
 
 use async_graphql::{EmptySubscription, Schema};
 use axum::{extract::Extension, http::StatusCode, response::Html, routing::get, Router, Server};
 use ethers::contract::abigen;
 
 use std::sync::Arc;
-use std::{collections::HashMap, fs};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+};
 use tokio::sync::RwLock;
 
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 type SharedMap = Arc<RwLock<HashMap<[u8; 32], Data>>>;
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
 struct Obj {
     status: String,
     request_id: Vec<u8>,
     op_index: Option<[u8; 32]>,
+    /// EigenDA quorums the blob was dispersed with; empty for other DAs.
+    security_params: Vec<SecurityParam>,
+    /// Header and verification proof reported by EigenDA once confirmed.
+    eigen_info: Option<EigenBlobInfo>,
+    /// How the EigenDA payload was encoded before dispersal; `None` for raw bytes.
+    codec: Option<u8>,
+    /// Native DA pointer (the bytes saved to the registry), once submitted.
+    pointer: Vec<u8>,
+    content_hash: Option<[u8; 32]>,
+    /// Why the submission failed, when `status` is `FAILED`.
+    error: Option<String>,
+    /// Transaction that published the pointer to an EVM registry.
+    registry_tx: Option<RegistryTx>,
+    tenant: Option<String>,
+    /// Tags given to `storeBlob`.
+    metadata: BTreeMap<String, String>,
+    /// Hash of `metadata` committed in the registry entry, when requested.
+    metadata_hash: Option<[u8; 32]>,
+    /// Why the DA was picked, for blobs stored with `da: AUTO`.
+    routing: Option<RoutingDecision>,
+    /// Each status the blob went through, on which DA, oldest first.
+    history: Vec<StatusChange>,
+    /// Submissions sent to DAs, oldest first.
+    attempts: Vec<Attempt>,
+    /// Fees paid for the DA submission and registry write, as reported by
+    /// each chain.
+    fees: Vec<Fee>,
+    /// Length of the submitted data in bytes.
+    size: u64,
+    /// Unix seconds; 0 for records written before timestamps were kept.
+    created_at: u64,
+    updated_at: u64,
     // hash: Option<Vec<u8>>,
     // index: Option<u32>,
 }
@@ -34,7 +91,7 @@ struct Obj {
 // }
 
 #[repr(u8)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 enum Data {
     Avail(Obj),
     EigenDA(Obj),
@@ -42,8 +99,124 @@ enum Data {
     Celestia(Obj),
 }
 
+impl Data {
+    fn new(da: DA, obj: Obj) -> Self {
+        match da {
+            DA::Avail => Data::Avail(obj),
+            DA::EigenDA => Data::EigenDA(obj),
+            DA::Near => Data::Near(obj),
+            DA::Celestia => Data::Celestia(obj),
+        }
+    }
+
+    fn da(&self) -> DA {
+        match self {
+            Data::Avail(_) => DA::Avail,
+            Data::EigenDA(_) => DA::EigenDA,
+            Data::Near(_) => DA::Near,
+            Data::Celestia(_) => DA::Celestia,
+        }
+    }
+
+    fn obj(&self) -> &Obj {
+        match self {
+            Data::Avail(obj) | Data::EigenDA(obj) | Data::Near(obj) | Data::Celestia(obj) => obj,
+        }
+    }
+
+    fn obj_mut(&mut self) -> &mut Obj {
+        match self {
+            Data::Avail(obj) | Data::EigenDA(obj) | Data::Near(obj) | Data::Celestia(obj) => obj,
+        }
+    }
+
+    fn into_obj(self) -> Obj {
+        match self {
+            Data::Avail(obj) | Data::EigenDA(obj) | Data::Near(obj) | Data::Celestia(obj) => obj,
+        }
+    }
+}
+
+/// Shared by every resolver; cheap to clone into background submission tasks.
+#[derive(Clone)]
 struct ApiContext {
     map: SharedMap,
+    /// Public id of each blob in `map` by registry index, for lookups by the
+    /// ids earlier versions returned and for the indexer.
+    ops: Arc<RwLock<HashMap<[u8; 32], [u8; 32]>>>,
+    /// Ids of EigenDA blobs whose pointer is being written to the registry,
+    /// so concurrent status polls publish it once.
+    publishing: Arc<std::sync::Mutex<HashSet<[u8; 32]>>>,
+    index: Arc<Index>,
+    registry: Arc<dyn PointerRegistry>,
+    quotas: Arc<Quotas>,
+    /// Retry policies and circuit breakers of the DAs and the registry.
+    backends: Arc<Backends>,
+    /// Latest balances of the accounts that pay for submissions.
+    accounts: Arc<Accounts>,
+    metrics: Arc<Metrics>,
+    settings: Arc<Settings>,
+}
+
+impl ApiContext {
+    /// Public id for a new blob: the hash of its content and a nonce, so the
+    /// same bytes stored twice get distinct ids and the id does not depend on
+    /// which DA the blob ends up on.
+    async fn new_blob_id(&self, data: &[u8]) -> [u8; 32] {
+        let map = self.map.read().await;
+        let mut nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        loop {
+            let id = hash_data(&concat(data, &nonce.to_le_bytes()));
+            if !map.contains_key(&id) {
+                return id;
+            }
+            nonce += 1;
+        }
+    }
+
+    /// Records `data` under `id` in the persistent index and the in-memory map,
+    /// stamping its creation and update times and noting a new status or DA
+    /// in its history. If the index cannot be written, neither is changed.
+    async fn insert(&self, id: [u8; 32], mut data: Data) -> Result<(), String> {
+        let now = unix_time();
+        let da = data.da();
+        let obj = data.obj_mut();
+        if obj.created_at == 0 {
+            obj.created_at = now;
+        }
+        obj.updated_at = now;
+        if obj
+            .history
+            .last()
+            .map_or(true, |last| last.status != obj.status || last.da != da)
+        {
+            obj.history.push(StatusChange {
+                status: obj.status.clone(),
+                da,
+                error: obj.error.clone(),
+                at: now,
+            });
+        }
+
+        self.index
+            .put(&id, &data)
+            .map_err(|e| format!("Could not write to blob index: {}", e))?;
+        if let Some(op_index) = data.obj().op_index {
+            self.ops.write().await.insert(op_index, id);
+        }
+        self.map.write().await.insert(id, data);
+        Ok(())
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
 async fn index_html() -> Result<Html<String>, (StatusCode, &'static str)> {
@@ -53,8 +226,18 @@ async fn index_html() -> Result<Html<String>, (StatusCode, &'static str)> {
     }
 }
 
-use model::{MutationRoot, QueryRoot};
-use routes::{graphql_handler, graphql_playground, health};
+use accounts::Accounts;
+use billing::Fee;
+use config::{RegistrySettings, Settings};
+use eigen::{EigenBlobInfo, SecurityParam};
+use index::Index;
+use metrics::Metrics;
+use model::{MutationRoot, QueryRoot, StatusChange, DA};
+use quota::Quotas;
+use registry::{PointerRegistry, RegistryTx};
+use retry::{Attempt, Backends};
+use routes::{graphql_handler, graphql_playground, health, metrics, ready, usage_csv};
+use routing::RoutingDecision;
 
 // Import the generated proto-rust file into a module
 pub mod disperser {
@@ -76,7 +259,6 @@ const TIA_AUTH_TOKEN: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJBbGxvdyI6W
 
 const OPSEP_CONTRACT: &str = "0x7334e5F4f1f57f097721D66142cFe17eD10Fbef1";
 const OPSEP_RPC: &str = "https://opt-sepolia.g.alchemy.com/v2/ATzASZn_CKT5Bz_hgG2zRHu5KXNV0b_S";
-const OPSET_SEED: &str = "0x496d9e930e4a133fc73b71314f8f7305be5d52eeb161f45e822bf07764b1a4be";
 
 abigen!(MapContract, "Map.json");
 /*
@@ -90,6 +272,13 @@ SECRET KEYPAIR: ed25519:552x5ak2HHHMMNPC3oqTeR6SMrkPUsa2kBQPYtCm4Z7XfkHRTTgpKdyf
 
 */
 
+fn concat(slice1: &[u8], slice2: &[u8]) -> Vec<u8> {
+    let mut concatenated = Vec::with_capacity(slice1.len() + slice2.len());
+    concatenated.extend_from_slice(slice1);
+    concatenated.extend_from_slice(slice2);
+    concatenated
+}
+
 fn hash_data(data: &[u8]) -> [u8; 32] {
     let mut hasher = Sha256::new();
     hasher.update(data);
@@ -100,15 +289,66 @@ fn hash_data(data: &[u8]) -> [u8; 32] {
 // Runtime to run our server
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let map: SharedMap = Arc::new(RwLock::new(HashMap::new()));
+    let settings = Settings::load()?;
+    let index = Arc::new(Index::open(&settings.database)?);
+    let metrics = Arc::new(Metrics::default());
+    let backends = Arc::new(Backends::new(settings.retry.clone(), metrics.clone()));
+    let registry = registry::from_settings(&settings.registry, index.clone(), backends.clone())?;
+    let map = index.load()?;
+    let ops = map
+        .iter()
+        .filter_map(|(id, data)| Some((data.obj().op_index?, *id)))
+        .collect();
+    let api_context = ApiContext {
+        map: Arc::new(RwLock::new(map)),
+        ops: Arc::new(RwLock::new(ops)),
+        publishing: Arc::default(),
+        index,
+        registry,
+        quotas: Arc::new(Quotas::default()),
+        backends,
+        accounts: Arc::new(Accounts::default()),
+        metrics,
+        settings: Arc::new(settings),
+    };
+
+    // `da index` only syncs the blob index from the registry contract; the
+    // server follows it in the background when `registry.indexer` is set.
+    let index_only = std::env::args().nth(1).as_deref() == Some("index");
+    if let RegistrySettings::Evm(evm) = &api_context.settings.registry {
+        if index_only {
+            let settings = evm.indexer.clone().unwrap_or_default();
+            indexer::run(api_context.clone(), evm.clone(), settings).await?;
+            return Ok(());
+        }
+        if let Some(settings) = evm.indexer.clone() {
+            let (api_context, evm) = (api_context.clone(), evm.clone());
+            tokio::spawn(async move {
+                if let Err(e) = indexer::run(api_context, evm, settings).await {
+                    eprintln!("Registry indexer stopped: {}", e);
+                }
+            });
+        }
+    } else if index_only {
+        return Err("The indexer needs an evm registry".into());
+    }
+
+    tokio::spawn(accounts::run(api_context.clone()));
+
+    let settings = api_context.settings.clone();
     let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
-        .data(ApiContext { map })
+        .data(api_context.clone())
         .finish();
     let app = Router::new()
         .route("/", get(index_html).post(graphql_handler))
         .route("/playground", get(graphql_playground).post(graphql_handler))
         .route("/health", get(health))
-        .layer(Extension(schema));
+        .route("/ready", get(ready))
+        .route("/metrics", get(metrics))
+        .route("/usage.csv", get(usage_csv))
+        .layer(Extension(schema))
+        .layer(Extension(api_context))
+        .layer(Extension(settings));
 
     Server::bind(&"0.0.0.0:8000".parse().unwrap())
         .serve(app.into_make_service())
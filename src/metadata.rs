@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use async_graphql::{InputObject, SimpleObject};
+use serde::{Deserialize, Serialize};
+
+use crate::hash_data;
+
+/// Leading byte of a registry entry whose pointer is prefixed with the hash of
+/// the blob's metadata: `METADATA_TAG | sha256(metadata)[32] | pointer`. DA
+/// tags are small, so the two never collide.
+const METADATA_TAG: u8 = 0x80;
+
+/// Leading byte of a registry entry that starts with the blob's public id:
+/// `ID_TAG | id[32] | rest`, where `rest` is a plain or metadata entry. It
+/// lets the indexer record blobs under the id `storeBlob` returned.
+const ID_TAG: u8 = 0x81;
+
+const MAX_TAGS: usize = 32;
+const MAX_KEY_LEN: usize = 64;
+const MAX_VALUE_LEN: usize = 1024;
+
+/// One metadata entry, e.g. `rollup_id`, `batch_number` or an arbitrary label.
+#[derive(SimpleObject, InputObject, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[graphql(input_name = "BlobTagInput")]
+pub(crate) struct BlobTag {
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
+
+/// Checks the tags given to `storeBlob` and collects them into the map kept
+/// on the blob record.
+pub(crate) fn collect(tags: Vec<BlobTag>) -> Result<BTreeMap<String, String>, String> {
+    if tags.len() > MAX_TAGS {
+        return Err(format!("At most {} metadata tags are allowed", MAX_TAGS));
+    }
+    let mut metadata = BTreeMap::new();
+    for BlobTag { key, value } in tags {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(format!(
+                "Metadata keys must be 1 to {} bytes long",
+                MAX_KEY_LEN
+            ));
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(format!(
+                "Metadata value for {} is longer than {} bytes",
+                key, MAX_VALUE_LEN
+            ));
+        }
+        if metadata.insert(key.clone(), value).is_some() {
+            return Err(format!("Duplicate metadata key {}", key));
+        }
+    }
+    Ok(metadata)
+}
+
+pub(crate) fn tags(metadata: &BTreeMap<String, String>) -> Vec<BlobTag> {
+    metadata
+        .iter()
+        .map(|(key, value)| BlobTag {
+            key: key.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+/// sha256 of the metadata as JSON; keys are sorted, so the encoding is stable.
+pub(crate) fn hash(metadata: &BTreeMap<String, String>) -> [u8; 32] {
+    hash_data(&serde_json::to_vec(metadata).expect("metadata serializes"))
+}
+
+/// A registry entry split into its parts.
+pub(crate) struct Entry<'a> {
+    /// Public id of the blob; unset in entries written by earlier versions.
+    pub(crate) id: Option<[u8; 32]>,
+    pub(crate) metadata_hash: Option<[u8; 32]>,
+    pub(crate) pointer: &'a [u8],
+}
+
+/// The registry entry for blob `id` at `ptr`, committing to the metadata
+/// when `hash` is set.
+pub(crate) fn wrap(id: [u8; 32], ptr: &[u8], hash: Option<[u8; 32]>) -> Vec<u8> {
+    let mut entry = vec![ID_TAG];
+    entry.extend_from_slice(&id);
+    if let Some(hash) = hash {
+        entry.push(METADATA_TAG);
+        entry.extend_from_slice(&hash);
+    }
+    entry.extend_from_slice(ptr);
+    entry
+}
+
+/// Splits a registry entry into the blob id and committed metadata hash, if
+/// any, and the DA pointer.
+pub(crate) fn unwrap(entry: &[u8]) -> Entry<'_> {
+    let (id, rest) = match entry.first() {
+        Some(&ID_TAG) if entry.len() > 33 => (Some(entry[1..33].try_into().unwrap()), &entry[33..]),
+        _ => (None, entry),
+    };
+    let (metadata_hash, pointer) = match rest.first() {
+        Some(&METADATA_TAG) if rest.len() > 33 => {
+            (Some(rest[1..33].try_into().unwrap()), &rest[33..])
+        }
+        _ => (None, rest),
+    };
+    Entry {
+        id,
+        metadata_hash,
+        pointer,
+    }
+}
@@ -0,0 +1,282 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use crate::{accounts::AccountBalance, retry::Backend, ApiContext, Data, DA};
+
+/// Upper bounds of the latency histograms, in seconds. Submissions that wait
+/// for finality and EigenDA confirmations take minutes.
+const BUCKETS: [f64; 14] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0,
+];
+
+/// Name, type and help of each recorded metric family.
+const FAMILIES: [(&str, &str, &str); 5] = [
+    (
+        "da_stage_total",
+        "counter",
+        "Blobs through each stage (submission, confirmation, registry, retrieval) by DA and outcome.",
+    ),
+    (
+        "da_stage_duration_seconds",
+        "histogram",
+        "Time each stage took; confirmation counts from when the blob was stored.",
+    ),
+    (
+        "da_submitted_bytes_total",
+        "counter",
+        "Bytes accepted by each DA.",
+    ),
+    (
+        "da_registry_gas_used_total",
+        "counter",
+        "Gas used by registry writes, by the DA of the blob.",
+    ),
+    (
+        "da_errors_total",
+        "counter",
+        "Failed backend calls by backend and error code.",
+    ),
+];
+
+type Labels = Vec<(&'static str, String)>;
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations at or below each of `BUCKETS`.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Recorded {
+    counters: BTreeMap<(&'static str, Labels), f64>,
+    histograms: BTreeMap<(&'static str, Labels), Histogram>,
+}
+
+/// Counters and histograms recorded since the process started.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl Metrics {
+    pub(crate) fn count(&self, name: &'static str, labels: &[(&'static str, &str)], by: f64) {
+        let mut recorded = self.recorded.lock().unwrap();
+        *recorded.counters.entry((name, owned(labels))).or_default() += by;
+    }
+
+    fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut recorded = self.recorded.lock().unwrap();
+        let histogram = recorded
+            .histograms
+            .entry((name, owned(labels)))
+            .or_default();
+        for (bucket, &bound) in histogram.buckets.iter_mut().zip(&BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    /// One pass of a blob on `da` through `stage`, counted by outcome and
+    /// timed.
+    pub(crate) fn stage(&self, stage: &str, da: DA, took: Duration, ok: bool) {
+        let outcome = if ok { "ok" } else { "failed" };
+        self.count(
+            "da_stage_total",
+            &[("stage", stage), ("da", da.name()), ("outcome", outcome)],
+            1.0,
+        );
+        self.observe(
+            "da_stage_duration_seconds",
+            &[("stage", stage), ("da", da.name())],
+            took.as_secs_f64(),
+        );
+    }
+
+    pub(crate) fn error(&self, backend: Backend, code: &str) {
+        self.count(
+            "da_errors_total",
+            &[("backend", backend.name()), ("code", code)],
+            1.0,
+        );
+    }
+}
+
+fn owned(labels: &[(&'static str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|&(key, value)| (key, value.to_string()))
+        .collect()
+}
+
+/// Metrics in the Prometheus text format.
+pub(crate) async fn render(api_context: &ApiContext) -> String {
+    let mut out = String::new();
+    {
+        let recorded = api_context.metrics.recorded.lock().unwrap();
+        for (name, kind, help) in FAMILIES {
+            header(&mut out, name, kind, help);
+            let counters = recorded
+                .counters
+                .iter()
+                .filter(|((family, _), _)| *family == name);
+            for ((_, labels), value) in counters {
+                sample(&mut out, name, labels, *value);
+            }
+            let histograms = recorded
+                .histograms
+                .iter()
+                .filter(|((family, _), _)| *family == name);
+            for ((_, labels), histogram) in histograms {
+                let mut bucket_labels = labels.clone();
+                bucket_labels.push(("le", String::new()));
+                let bucket = format!("{}_bucket", name);
+                for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+                    bucket_labels.last_mut().unwrap().1 = bound.to_string();
+                    sample(&mut out, &bucket, &bucket_labels, *count as f64);
+                }
+                bucket_labels.last_mut().unwrap().1 = "+Inf".to_string();
+                sample(&mut out, &bucket, &bucket_labels, histogram.count as f64);
+                sample(&mut out, &format!("{}_sum", name), labels, histogram.sum);
+                sample(
+                    &mut out,
+                    &format!("{}_count", name),
+                    labels,
+                    histogram.count as f64,
+                );
+            }
+        }
+    }
+
+    let (blobs, pending) = {
+        let map = api_context.map.read().await;
+        let pending = map
+            .values()
+            .filter(|data| match data {
+                Data::EigenDA(obj) => {
+                    !obj.request_id.is_empty()
+                        && !matches!(obj.status.as_str(), "FINALIZED" | "CONFIRMED" | "FAILED")
+                }
+                _ => false,
+            })
+            .count();
+        (map.len(), pending)
+    };
+    header(
+        &mut out,
+        "da_pending_eigenda_dispersals",
+        "gauge",
+        "EigenDA dispersals not yet confirmed or failed.",
+    );
+    sample(
+        &mut out,
+        "da_pending_eigenda_dispersals",
+        &[],
+        pending as f64,
+    );
+    header(
+        &mut out,
+        "da_map_blobs",
+        "gauge",
+        "Blob records held in memory.",
+    );
+    sample(&mut out, "da_map_blobs", &[], blobs as f64);
+    match api_context.index.sizes() {
+        Ok((blobs, pointers)) => {
+            header(
+                &mut out,
+                "da_index_blobs",
+                "gauge",
+                "Blob records in the SQLite index.",
+            );
+            sample(&mut out, "da_index_blobs", &[], blobs as f64);
+            header(
+                &mut out,
+                "da_index_pointers",
+                "gauge",
+                "Pointers saved by the local registry.",
+            );
+            sample(&mut out, "da_index_pointers", &[], pointers as f64);
+        }
+        Err(e) => eprintln!("Could not read index size: {}", e),
+    }
+
+    let accounts = api_context.accounts.latest();
+    header(
+        &mut out,
+        "da_account_balance",
+        "gauge",
+        "Balance of an account that pays for submissions, in whole tokens.",
+    );
+    for account in accounts.iter().filter(|a| a.tokens.is_some()) {
+        sample(
+            &mut out,
+            "da_account_balance",
+            &account_labels(account),
+            account.tokens.unwrap_or_default(),
+        );
+    }
+    header(
+        &mut out,
+        "da_account_low",
+        "gauge",
+        "1 when an account is below its accounts.min_balance.",
+    );
+    for account in &accounts {
+        sample(
+            &mut out,
+            "da_account_low",
+            &account_labels(account),
+            f64::from(u8::from(account.low)),
+        );
+    }
+    header(
+        &mut out,
+        "da_account_check_failed",
+        "gauge",
+        "1 when the last balance lookup of an account failed.",
+    );
+    for account in &accounts {
+        sample(
+            &mut out,
+            "da_account_check_failed",
+            &account_labels(account),
+            f64::from(u8::from(account.error.is_some())),
+        );
+    }
+    out
+}
+
+fn account_labels(account: &AccountBalance) -> Labels {
+    vec![
+        ("backend", account.backend.name().to_string()),
+        ("address", account.address.clone().unwrap_or_default()),
+    ]
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, String)], value: f64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+        return;
+    }
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect();
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,64 +1,127 @@
-use std::{str::FromStr, sync::Arc};
-
-use async_graphql::{Context, EmptySubscription, Enum, Object, Schema};
-use celestia_rpc::{BlobClient, Client as Tia};
-use celestia_types::{
-    blob::SubmitOptions, nmt::Namespace as TiaNamespace, Blob as TiaBlob, Commitment,
-};
-use ethers::{
-    middleware::SignerMiddleware,
-    providers::{Http, Provider},
-    signers::{LocalWallet, Signer},
-    types::Address,
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
 };
-use near_da_rpc::{
-    near::{
-        config::{Config, KeyType, Network},
-        Client,
-    },
-    Blob, CryptoHash, DataAvailability, Namespace,
+
+use async_graphql::{
+    connection::{Connection, CursorType, Edge},
+    Context, EmptySubscription, Enum, InputObject, Json, Object, Schema, SimpleObject,
 };
+use ethers::utils::hex;
 use serde::{Deserialize, Serialize};
-use tonic::Status;
+use serde_json::Value;
 
 use crate::{
-    disperser::{
-        disperser_client::DisperserClient, BlobStatus as EigenBlobStatus, BlobStatusRequest,
-        DisperseBlobReply, DisperseBlobRequest, RetrieveBlobRequest, SecurityParams,
-    },
-    hash_data, ApiContext, Data, MapContract, Obj, AVAIL_SEED, AVAIL_SERVER, EIGEN_SERVER,
-    NEAR_ACCOUNT_ID, NEAR_SECRET, OPSEP_CONTRACT, OPSEP_RPC, OPSET_SEED,
-};
-use crate::{TIA_AUTH_TOKEN, TIA_SERVER};
-
-use avail_subxt::{
-    api::{
-        self,
-        runtime_types::{
-            bounded_collections::bounded_vec::BoundedVec, da_control::pallet::Call as DaCall,
-        },
-    },
-    avail::{AppUncheckedExtrinsic, Pair},
-    build_client,
-    primitives::AvailExtrinsicParams,
-    Call,
+    accounts::{self, AccountBalance},
+    auth, avail,
+    billing::{self, DailyUsage, Fee},
+    celestia, concat,
+    disperser::BlobStatus as EigenBlobStatus,
+    eigen::{self, EigenBlobInfo, SecurityParam},
+    estimate::{self, CostEstimate, MAX_ESTIMATE_SIZE},
+    hash_data,
+    index::BlobQuery,
+    metadata::{self, BlobTag},
+    near::{self, NearPointer},
+    near_proof,
+    quota::Quota,
+    registry::{RegistryError, RegistryTx, SavedPointer},
+    retry::{Attempt, Backend, Failure, Landed},
+    routing::{self, RoutingDecision},
+    unix_time, ApiContext, Data, Obj,
 };
 
-use sp_core::{Pair as _, H256};
-use subxt::tx::PairSigner;
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DA {
+    Avail,
+    EigenDA,
+    Near,
+    Celestia,
+}
+
+impl DA {
+    pub(crate) const ALL: [DA; 4] = [DA::Avail, DA::EigenDA, DA::Near, DA::Celestia];
+
+    /// Name used for the DA in config files.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            DA::Avail => "avail",
+            DA::EigenDA => "eigenda",
+            DA::Near => "near",
+            DA::Celestia => "celestia",
+        }
+    }
+
+    /// The DA called `name` in config files.
+    pub(crate) fn from_name(name: &str) -> Option<DA> {
+        DA::ALL.into_iter().find(|da| da.name() == name)
+    }
+
+    /// The DA a registry pointer belongs to, from its leading tag byte.
+    pub(crate) fn from_tag(tag: u8) -> Option<DA> {
+        DA::ALL.into_iter().find(|&da| da as u8 == tag)
+    }
+}
+
+/// One entry of a blob's status history.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct StatusChange {
+    pub(crate) status: String,
+    pub(crate) da: DA,
+    /// Why the attempt failed, for `FAILED` entries.
+    pub(crate) error: Option<String>,
+    /// Unix seconds.
+    pub(crate) at: u64,
+}
 
+/// The `da` argument of `storeBlob`: a DA, or `AUTO` to let the routing
+/// policy pick one.
 #[derive(Enum, Copy, Clone, Eq, PartialEq)]
-pub enum DA {
+enum DaChoice {
     Avail,
     EigenDA,
     Near,
     Celestia,
+    Auto,
+}
+
+impl DaChoice {
+    fn da(self) -> Option<DA> {
+        match self {
+            DaChoice::Avail => Some(DA::Avail),
+            DaChoice::EigenDA => Some(DA::EigenDA),
+            DaChoice::Near => Some(DA::Near),
+            DaChoice::Celestia => Some(DA::Celestia),
+            DaChoice::Auto => None,
+        }
+    }
+}
+
+/// Result of `storeBlob`.
+#[derive(SimpleObject)]
+struct StoredBlob {
+    /// Valid immediately for `getBlobStatus` and `getBlobData`.
+    id: [u8; 32],
+    /// The DA the blob is being submitted to.
+    da: DA,
+    /// Why `da` was picked, when `AUTO` was asked for.
+    routing: Option<RoutingDecision>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 struct BlobStatus {
     status: String,
     index: Option<[u8; 32]>,
+    security_params: Vec<SecurityParam>,
+    error: Option<String>,
+    registry_tx: Option<RegistryTx>,
+    metadata: Vec<BlobTag>,
+    fees: Vec<Fee>,
+    da: Option<DA>,
+    routing: Option<RoutingDecision>,
+    history: Vec<StatusChange>,
+    attempts: Vec<Attempt>,
 }
 
 #[Object]
@@ -67,437 +130,988 @@ impl BlobStatus {
         &self.status
     }
 
+    /// Index of the blob's pointer in the registry contract, once written.
     async fn index(&self) -> &Option<[u8; 32]> {
         &self.index
     }
+
+    /// EigenDA quorums the blob was dispersed with.
+    async fn security_params(&self) -> &Vec<SecurityParam> {
+        &self.security_params
+    }
+
+    /// Why the submission failed, when `status` is `FAILED`.
+    async fn error(&self) -> &Option<String> {
+        &self.error
+    }
+
+    /// Hash and outcome of the registry transaction, for EVM registries.
+    async fn registry_tx(&self) -> &Option<RegistryTx> {
+        &self.registry_tx
+    }
+
+    /// Tags the blob was stored with.
+    async fn metadata(&self) -> &Vec<BlobTag> {
+        &self.metadata
+    }
+
+    /// Fees paid so far for the DA submission and registry write.
+    async fn fees(&self) -> &Vec<Fee> {
+        &self.fees
+    }
+
+    /// Why the DA was picked, for blobs stored with `da: AUTO`.
+    async fn routing(&self) -> &Option<RoutingDecision> {
+        &self.routing
+    }
+
+    /// Statuses the blob went through, oldest first. A submission that
+    /// failed over to another DA shows a `FAILED` entry for each DA tried
+    /// before the last.
+    async fn history(&self) -> &Vec<StatusChange> {
+        &self.history
+    }
+
+    /// Submissions sent to DAs, oldest first, with their transaction where
+    /// it is known.
+    async fn attempts(&self) -> &Vec<Attempt> {
+        &self.attempts
+    }
+
+    /// The DA the blob is on, or is being submitted to.
+    async fn da(&self) -> Option<DA> {
+        self.da
+    }
+}
+
+impl From<Data> for BlobStatus {
+    fn from(data: Data) -> Self {
+        let da = data.da();
+        let obj = data.into_obj();
+        BlobStatus {
+            da: Some(da),
+            status: obj.status,
+            index: obj.op_index,
+            security_params: obj.security_params,
+            error: obj.error,
+            registry_tx: obj.registry_tx,
+            metadata: metadata::tags(&obj.metadata),
+            fees: obj.fees,
+            routing: obj.routing,
+            history: obj.history,
+            attempts: obj.attempts,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct NearBlobProof {
+    /// Base58 hash of the submission transaction.
+    tx_hash: String,
+    sender_id: String,
+    /// Base58 `block_merkle_root` of the light client head the proof targets.
+    block_merkle_root: String,
+    /// Raw `EXPERIMENTAL_light_client_proof` result.
+    proof: Json<Value>,
+    verified: bool,
+}
+
+/// One row of the `blobs` listing.
+#[derive(SimpleObject)]
+struct BlobSummary {
+    id: [u8; 32],
+    da: DA,
+    status: String,
+    tenant: Option<String>,
+    /// Size of the submitted data in bytes.
+    size: u64,
+    content_hash: Option<[u8; 32]>,
+    metadata: Vec<BlobTag>,
+    /// Unix seconds; 0 for blobs stored before timestamps were recorded.
+    created_at: u64,
+    updated_at: u64,
+}
+
+impl BlobSummary {
+    fn new(id: [u8; 32], data: Data) -> Self {
+        let da = data.da();
+        let obj = data.into_obj();
+        BlobSummary {
+            id,
+            da,
+            status: obj.status,
+            tenant: obj.tenant,
+            size: obj.size,
+            content_hash: obj.content_hash,
+            metadata: metadata::tags(&obj.metadata),
+            created_at: obj.created_at,
+            updated_at: obj.updated_at,
+        }
+    }
+}
+
+/// Narrows `blobs`; unset fields match every blob. `from` and `to` bound the
+/// creation time in unix seconds, inclusively.
+#[derive(InputObject, Default)]
+struct BlobListFilter {
+    da: Option<DA>,
+    /// `PROCESSING`, `FINALIZED`, `FAILED` or an EigenDA status; any case.
+    status: Option<String>,
+    tenant: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+    /// Metadata entries the blob must all have.
+    tags: Option<Vec<BlobTag>>,
+}
+
+/// Position in the `blobs` listing: creation time and id of the last blob seen.
+struct BlobCursor {
+    created_at: u64,
+    id: [u8; 32],
 }
 
-fn concatenate_slices(slice1: &[u8], slice2: &[u8]) -> Vec<u8> {
-    let mut concatenated = Vec::with_capacity(slice1.len() + slice2.len());
-    concatenated.extend_from_slice(slice1);
-    concatenated.extend_from_slice(slice2);
-    concatenated
+impl CursorType for BlobCursor {
+    type Error = String;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let bytes = hex::decode(s).map_err(|_| "Invalid cursor".to_string())?;
+        if bytes.len() != 40 {
+            return Err("Invalid cursor".to_string());
+        }
+        Ok(BlobCursor {
+            created_at: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+            id: bytes[8..].try_into().unwrap(),
+        })
+    }
+
+    fn encode_cursor(&self) -> String {
+        hex::encode(concat(&self.created_at.to_be_bytes(), &self.id))
+    }
 }
 
+/// Most blobs returned by one `blobs` page.
+const MAX_PAGE: usize = 100;
+
 pub(crate) type ServiceSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
 pub(crate) struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
-    async fn get_blob_status(&self, ctx: &Context<'_>, id: [u8; 32]) -> BlobStatus {
+    async fn get_blob_status(
+        &self,
+        ctx: &Context<'_>,
+        id: [u8; 32],
+    ) -> async_graphql::Result<BlobStatus> {
         let api_context = ctx.data_unchecked::<ApiContext>();
-        let map = api_context.map.read().await;
-        let data = map.get(&id);
+        let data = visible_record(ctx, id).await;
 
-        if data.is_none() {
-            return BlobStatus {
+        match data {
+            None => Ok(BlobStatus {
                 status: "Not found, try fetching data if ID is valid".to_string(),
                 index: None,
+                security_params: vec![],
+                error: None,
+                registry_tx: None,
+                metadata: vec![],
+                fees: vec![],
+                da: None,
+                routing: None,
+                history: vec![],
+                attempts: vec![],
+            }),
+            Some(Data::EigenDA(eigen_da)) => {
+                Ok(Data::EigenDA(refresh_eigen_status(api_context, id, eigen_da).await?).into())
+            }
+            Some(data) => Ok(data.into()),
+        }
+    }
+
+    /// Stored blobs, newest first, for dashboards. Pass the last `endCursor` as
+    /// `after` to get the next page.
+    async fn blobs(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<BlobListFilter>,
+        #[graphql(desc = "Page size, at most 100", default = 20)] first: usize,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<BlobCursor, BlobSummary>> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let filter = filter.unwrap_or_default();
+        let tenant = auth::scope(&api_context.settings, auth::caller(ctx), filter.tenant)?;
+        let query = BlobQuery {
+            da: filter.da.map(|da| da as u8),
+            status: filter.status.map(|status| status.to_uppercase()),
+            tenant,
+            from: filter.from,
+            to: filter.to,
+            tags: filter
+                .tags
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tag| (tag.key, tag.value))
+                .collect(),
+        };
+        let after = after
+            .map(|cursor| BlobCursor::decode_cursor(&cursor))
+            .transpose()?
+            .map(|cursor| (cursor.created_at, cursor.id));
+        let first = first.min(MAX_PAGE);
+
+        // One extra row tells whether there is a next page.
+        let mut rows = api_context.index.list(&query, after, first + 1)?;
+        let has_next = rows.len() > first;
+        rows.truncate(first);
+
+        let mut connection = Connection::new(after.is_some(), has_next);
+        connection.edges.extend(rows.into_iter().map(|(id, data)| {
+            let cursor = BlobCursor {
+                created_at: data.obj().created_at,
+                id,
             };
+            Edge::new(cursor, BlobSummary::new(id, data))
+        }));
+        Ok(connection)
+    }
+
+    /// Blobs stored and fees paid per UTC day and DA, oldest first, for blobs
+    /// created between `from` and `to` (unix seconds, inclusive).
+    async fn usage(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<u64>,
+        to: Option<u64>,
+        tenant: Option<String>,
+    ) -> async_graphql::Result<Vec<DailyUsage>> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let tenant = auth::scope(&api_context.settings, auth::caller(ctx), tenant)?;
+        Ok(billing::usage(api_context, tenant.as_deref(), from, to)?)
+    }
+
+    /// What storing `size` bytes would cost right now on each of `da` (every
+    /// DA when omitted), including the registry write, cheapest first.
+    /// Amounts are in each chain's smallest unit and, with `prices`
+    /// configured, in USD. Needs the same credentials as `storeBlob`.
+    async fn estimate_cost(
+        &self,
+        ctx: &Context<'_>,
+        size: u64,
+        da: Option<Vec<DA>>,
+    ) -> async_graphql::Result<Vec<CostEstimate>> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let tenant = auth::writer(ctx)?;
+        if size == 0 || size > MAX_ESTIMATE_SIZE {
+            return Err(format!("size must be between 1 and {} bytes", MAX_ESTIMATE_SIZE).into());
+        }
+        let mut das = vec![];
+        for da in da.unwrap_or_else(|| DA::ALL.to_vec()) {
+            if !das.contains(&da) {
+                das.push(da);
+            }
+        }
+        Ok(estimate::compare(api_context, tenant.as_deref(), &das, size).await)
+    }
+
+    /// Balances of the accounts that pay for submissions, from the last
+    /// periodic check, or checked now with `refresh`. Operators only.
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        refresh: Option<bool>,
+    ) -> async_graphql::Result<Vec<AccountBalance>> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        auth::operator(ctx)?;
+        if refresh.unwrap_or(false) {
+            return Ok(accounts::refresh(api_context).await);
         }
+        Ok(api_context.accounts.latest())
+    }
+
+    /// The caller's `storeBlob` limits and how much of each is used.
+    async fn quota(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Quota>> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let tenant = auth::writer(ctx)?;
+        Ok(api_context.quotas.report(api_context, tenant.as_deref())?)
+    }
+
+    /// Header, quorum parameters and inclusion proof of a confirmed EigenDA
+    /// blob, for verification against EigenDAServiceManager. `null` until the
+    /// disperser reports the blob as confirmed.
+    async fn eigen_blob_info(
+        &self,
+        ctx: &Context<'_>,
+        id: [u8; 32],
+    ) -> async_graphql::Result<Option<EigenBlobInfo>> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let data = visible_record(ctx, id).await;
 
-        let data = data.unwrap().clone();
-        drop(map);
         match data {
-            Data::Celestia(tia) => BlobStatus {
-                status: tia.status,
-                index: tia.op_index,
-            },
-            Data::Avail(avail) => BlobStatus {
-                status: avail.status,
-                index: avail.op_index,
-            },
-            Data::Near(near) => BlobStatus {
-                status: near.status,
-                index: near.op_index,
-            },
-            Data::EigenDA(eigen_da) => {
-                if eigen_da.status == *"FINALIZED" || eigen_da.status == *"CONFIRMED" {
-                    return BlobStatus {
-                        status: eigen_da.status,
-                        index: eigen_da.op_index,
-                    };
-                }
-                let request_id = eigen_da.request_id;
-
-                let request = BlobStatusRequest {
-                    request_id: request_id.clone(),
-                };
-
-                let mut client = DisperserClient::connect(EIGEN_SERVER)
-                    .await
-                    .map_err(|e| {
-                        Status::internal(format!("Failed to connect to external service: {}", e))
-                    })
-                    .unwrap();
-
-                let response = client
-                    .get_blob_status(request)
-                    .await
-                    .map_err(|e| {
-                        Status::internal(format!(
-                            "Failed to send request to external service: {}",
-                            e
-                        ))
-                    })
-                    .unwrap();
-
-                let response = response.into_inner();
-
-                let status = response.status();
-                match status {
-                    EigenBlobStatus::Finalized | EigenBlobStatus::Confirmed => {
-                        let info = response
-                            .info
-                            .unwrap_or_default()
-                            .blob_verification_proof
-                            .unwrap_or_default();
-
-                        let mut hash = info.batch_metadata.unwrap_or_default().batch_header_hash;
-                        let mut index = Vec::from(info.blob_index.to_le_bytes());
-                        let owner = OPSET_SEED.parse::<LocalWallet>().unwrap();
-                        let op_provider = Provider::<Http>::try_from(OPSEP_RPC).unwrap();
-
-                        let op_client =
-                            SignerMiddleware::new(op_provider, owner.with_chain_id(11155420u64));
-                        let contract_address = OPSEP_CONTRACT.parse::<Address>().unwrap();
-                        let contract = MapContract::new(contract_address, Arc::new(op_client));
-                        hash.insert(0, DA::EigenDA as u8);
-                        hash.append(&mut index);
-                        let tx = contract
-                            .save(hash.into())
-                            .send()
-                            .await
-                            .unwrap()
-                            .await
-                            .unwrap()
-                            .unwrap();
-
-                        let op_index = tx.logs[0].topics[1].into();
-
-                        let mut map = api_context.map.write().await;
-                        map.insert(
-                            id,
-                            Data::EigenDA(Obj {
-                                status: status.as_str_name().to_string(),
-                                request_id: id.into(),
-                                op_index: Some(op_index),
-                            }),
-                        );
-                        drop(map);
-
-                        BlobStatus {
-                            status: status.as_str_name().to_string(),
-                            index: Some(op_index),
-                        }
-                    }
-                    _ => BlobStatus {
-                        status: status.as_str_name().to_string(),
-                        index: None,
-                    },
+            Some(Data::EigenDA(eigen_da)) => Ok(refresh_eigen_status(api_context, id, eigen_da)
+                .await?
+                .eigen_info),
+            _ => Ok(None),
+        }
+    }
+
+    /// Blob contents by the id returned from `storeBlob`. Registry indexes
+    /// returned by earlier versions are still accepted.
+    async fn get_blob_data(
+        &self,
+        ctx: &Context<'_>,
+        id: [u8; 32],
+    ) -> async_graphql::Result<String> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let ptr = resolve_pointer(ctx, id).await?;
+        let data = fetch_blob(api_context, &ptr).await?;
+        Ok(String::from_utf8(data)?)
+    }
+
+    /// Light-client execution outcome proof for the transaction that submitted
+    /// a NEAR blob. `head` is a block hash known to the verifying light client;
+    /// the latest final block is used when omitted.
+    async fn near_blob_proof(
+        &self,
+        ctx: &Context<'_>,
+        id: [u8; 32],
+        head: Option<String>,
+    ) -> async_graphql::Result<NearBlobProof> {
+        let api_context = ctx.data_unchecked::<ApiContext>();
+        let ptr = resolve_pointer(ctx, id).await?;
+        let near_ptr = NearPointer::decode(&ptr, &api_context.settings.near)
+            .ok_or("Blob was not stored on NEAR")?;
+
+        let (proof, block_merkle_root) = near_proof::fetch(&near_ptr, head).await?;
+        let verified = near_proof::verify(&proof, &near_ptr.tx_hash, &block_merkle_root).is_ok();
+
+        Ok(NearBlobProof {
+            tx_hash: bs58::encode(near_ptr.tx_hash).into_string(),
+            sender_id: near_ptr.signer,
+            block_merkle_root,
+            proof: Json(proof),
+            verified,
+        })
+    }
+
+    /// Checks a proof from `nearBlobProof` against a light client head's block
+    /// merkle root. Returns the reason when verification fails.
+    async fn verify_near_proof(
+        &self,
+        proof: Json<Value>,
+        tx_hash: String,
+        block_merkle_root: String,
+    ) -> async_graphql::Result<bool> {
+        let tx_hash: [u8; 32] = bs58::decode(&tx_hash)
+            .into_vec()
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or("Invalid transaction hash")?;
+        near_proof::verify(&proof.0, &tx_hash, &block_merkle_root)?;
+        Ok(true)
+    }
+}
+
+/// Polls the disperser for a blob that has not been confirmed yet. Once it is,
+/// the pointer is saved to the registry and the blob info is persisted.
+async fn refresh_eigen_status(
+    api_context: &ApiContext,
+    id: [u8; 32],
+    eigen_da: Obj,
+) -> Result<Obj, String> {
+    if eigen_da.status == *"FINALIZED"
+        || eigen_da.status == *"CONFIRMED"
+        || eigen_da.status == *"FAILED"
+        || eigen_da.request_id.is_empty()
+    {
+        return Ok(eigen_da);
+    }
+
+    let server = &api_context.settings.eigenda.server;
+    let response = api_context
+        .backends
+        .call(Backend::EigenDA, || {
+            eigen::status(server, eigen_da.request_id.clone())
+        })
+        .await?;
+
+    let status = response.status();
+    match status {
+        EigenBlobStatus::Finalized | EigenBlobStatus::Confirmed => {
+            // Another poll may be publishing the pointer, or have just done
+            // so; either way this one must not send a second write.
+            let Some(_publishing) = Publishing::claim(api_context, id) else {
+                return Ok(eigen_da);
+            };
+            if let Some(Data::EigenDA(current)) = api_context.map.read().await.get(&id) {
+                if matches!(current.status.as_str(), "FINALIZED" | "CONFIRMED") {
+                    return Ok(current.clone());
                 }
             }
+
+            let info = response.info.unwrap_or_default();
+            let pointer = eigen::pointer(&info, eigen_da.codec);
+            let entry = metadata::wrap(id, &pointer, eigen_da.metadata_hash);
+            let prior = eigen_da.registry_tx.clone();
+            let saved = match publish(api_context, DA::EigenDA, entry, prior).await {
+                Ok(saved) => saved,
+                Err(e) => {
+                    // Keep the failed transaction, so the next poll checks it
+                    // before sending another, and its fee, which was paid.
+                    let mut eigen_da = Obj {
+                        registry_tx: e.tx.clone().or(eigen_da.registry_tx),
+                        ..eigen_da
+                    };
+                    eigen_da.fees.extend(Fee::registry(e.tx.as_ref()));
+                    api_context.insert(id, Data::EigenDA(eigen_da)).await?;
+                    return Err(e.message);
+                }
+            };
+
+            let mut eigen_da = Obj {
+                status: status.as_str_name().to_string(),
+                op_index: saved.index,
+                registry_tx: saved.tx.clone(),
+                eigen_info: Some(info.into()),
+                pointer,
+                ..eigen_da
+            };
+            eigen_da.fees.extend(Fee::registry(saved.tx.as_ref()));
+            confirmed(api_context, DA::EigenDA, &eigen_da, true);
+            api_context
+                .insert(id, Data::EigenDA(eigen_da.clone()))
+                .await?;
+            Ok(eigen_da)
         }
+        _ => Ok(Obj {
+            status: status.as_str_name().to_string(),
+            ..eigen_da
+        }),
     }
+}
 
-    async fn get_blob_data(&self, _ctx: &Context<'_>, id: [u8; 32]) -> String {
-        let op_provider = Provider::<Http>::try_from(OPSEP_RPC).unwrap();
+/// Marks an EigenDA blob as being published until dropped.
+struct Publishing<'a> {
+    api_context: &'a ApiContext,
+    id: [u8; 32],
+}
 
-        let owner = OPSET_SEED.parse::<LocalWallet>().unwrap();
-        let op_client = SignerMiddleware::new(op_provider, owner.with_chain_id(11155420u64));
-        let contract_address = OPSEP_CONTRACT.parse::<Address>().unwrap();
-        let contract = MapContract::new(contract_address, Arc::new(op_client));
-        let data = contract.get(id.into()).call().await.unwrap();
-        let ptr: Vec<u8> = data.0.into();
+impl<'a> Publishing<'a> {
+    /// `None` while another poll is publishing `id`.
+    fn claim(api_context: &'a ApiContext, id: [u8; 32]) -> Option<Self> {
+        let claimed = api_context.publishing.lock().unwrap().insert(id);
+        claimed.then_some(Publishing { api_context, id })
+    }
+}
 
-        if ptr[0] == DA::Celestia as u8 {
-            let height: [u8; 8] = ptr[1..9].try_into().unwrap();
-            let commitment: [u8; 32] = ptr[9..].try_into().unwrap();
+impl Drop for Publishing<'_> {
+    fn drop(&mut self) {
+        self.api_context.publishing.lock().unwrap().remove(&self.id);
+    }
+}
 
-            let client = Tia::new(TIA_SERVER, Some(TIA_AUTH_TOKEN)).await.unwrap();
-            let blob = client
-                .blob_get(
-                    u64::from_le_bytes(height),
-                    TiaNamespace::const_v0(one_bytes_array()),
-                    Commitment(commitment),
-                )
-                .await
-                .unwrap();
-            String::from_utf8(blob.data).unwrap()
-        } else if ptr[0] == DA::Near as u8 {
-            let cryptohash: [u8; 32] = ptr[1..33].try_into().unwrap();
-            let near_client = Client::new(&Config {
-                key: KeyType::SecretKey(NEAR_ACCOUNT_ID.to_string(), NEAR_SECRET.to_string()),
-                network: Network::Testnet,
-                namespace: Namespace::new(1, 1),
-                contract: NEAR_ACCOUNT_ID.to_string(),
-            });
+/// The record for `id`, unless it belongs to another tenant.
+async fn visible_record(ctx: &Context<'_>, id: [u8; 32]) -> Option<Data> {
+    let api_context = ctx.data_unchecked::<ApiContext>();
+    let data = api_context.map.read().await.get(&id).cloned()?;
+    auth::can_see(ctx, data.obj()).then_some(data)
+}
 
-            let data = near_client
-                .get(CryptoHash(cryptohash))
+/// Pointer for `id`, taken from the index. Ids that are not in the index are
+/// treated as registry indexes, which is what `storeBlob` used to return;
+/// the blob recorded at that index, if any, must be visible to the caller.
+/// EigenDA blobs still being dispersed are polled first, as `getBlobStatus`
+/// would.
+async fn resolve_pointer(ctx: &Context<'_>, id: [u8; 32]) -> Result<Vec<u8>, String> {
+    let api_context = ctx.data_unchecked::<ApiContext>();
+    let record = {
+        let map = api_context.map.read().await;
+        let ops = api_context.ops.read().await;
+        let id = match map.contains_key(&id) {
+            true => Some(id),
+            false => ops.get(&id).copied(),
+        };
+        id.and_then(|id| Some((id, map.get(&id)?.clone())))
+    };
+    let data = match record {
+        Some((_, data)) if !auth::can_see(ctx, data.obj()) => return Err("Not found".to_string()),
+        Some((id, Data::EigenDA(eigen_da))) if eigen_da.pointer.is_empty() => Some(Data::EigenDA(
+            refresh_eigen_status(api_context, id, eigen_da).await?,
+        )),
+        record => record.map(|(_, data)| data),
+    };
+    let entry = match data.map(Data::into_obj) {
+        Some(obj) if !obj.pointer.is_empty() => return Ok(obj.pointer),
+        Some(Obj {
+            op_index: Some(op_index),
+            ..
+        }) => api_context.registry.get(op_index).await?,
+        Some(obj) => return Err(format!("Blob is not available yet ({})", obj.status)),
+        None => api_context.registry.get(id).await?,
+    };
+    Ok(metadata::unwrap(&entry).pointer.to_vec())
+}
+
+/// Reads are always safe to repeat, so every failure to reach the DA is
+/// retried.
+async fn fetch_blob(api_context: &ApiContext, ptr: &[u8]) -> Result<Vec<u8>, String> {
+    let Some(da) = ptr.first().copied().and_then(DA::from_tag) else {
+        return Err("Not found".to_string());
+    };
+    let backends = &api_context.backends;
+    let started = Instant::now();
+    let result = match da {
+        DA::Celestia => backends.call(da.into(), || celestia::get(ptr)).await,
+        DA::Near => {
+            let defaults = &api_context.settings.near;
+            let near_ptr = NearPointer::decode(ptr, defaults).ok_or("Malformed NEAR pointer")?;
+            backends
+                .call(da.into(), || near::get(&near_ptr, defaults))
                 .await
-                .unwrap()
-                .0
-                .data;
-            String::from_utf8(data).unwrap()
-        } else if ptr[0] == DA::Avail as u8 {
-            let hash: [u8; 32] = ptr[1..33].try_into().unwrap();
-            let index: [u8; 4] = ptr[33..].try_into().unwrap();
-            let client = build_client(AVAIL_SERVER, true).await.unwrap();
-
-            let submitted_block = client
-                .rpc()
-                .block(Some(H256::from(hash)))
+        }
+        DA::Avail => backends.call(da.into(), || avail::get(ptr)).await,
+        DA::EigenDA => {
+            let server = &api_context.settings.eigenda.server;
+            backends
+                .call(da.into(), || eigen::retrieve(server, ptr))
                 .await
-                .unwrap()
-                .unwrap();
+        }
+    };
+    let metrics = &api_context.metrics;
+    metrics.stage("retrieval", da, started.elapsed(), result.is_ok());
+    result
+}
 
-            let x = submitted_block
-                .block
-                .extrinsics
-                .into_iter()
-                .nth(u32::from_le_bytes(index) as usize)
-                .map(|chain_block_ext| {
-                    AppUncheckedExtrinsic::try_from(chain_block_ext)
-                        .map(|ext| ext.function)
-                        .ok()
-                })
-                .unwrap()
-                .unwrap();
-
-            match x {
-                Call::DataAvailability(DaCall::submit_data { data }) => {
-                    String::from_utf8(data.0).unwrap()
-                }
-                _ => "".to_owned(),
-            }
-        } else if ptr[0] == DA::EigenDA as u8 {
-            let hash: [u8; 32] = ptr[1..33].try_into().unwrap();
-            let index: [u8; 4] = ptr[33..].try_into().unwrap();
+/// Publishes `entry` for a blob stored on `da`, recording how long the
+/// write took and the gas it used. `prior` is the blob's last registry
+/// transaction, from a write that failed after it was sent.
+async fn publish(
+    api_context: &ApiContext,
+    da: DA,
+    entry: Vec<u8>,
+    prior: Option<RegistryTx>,
+) -> Result<SavedPointer, RegistryError> {
+    let started = Instant::now();
+    let result = api_context.registry.save(entry, prior).await;
+    let metrics = &api_context.metrics;
+    metrics.stage("registry", da, started.elapsed(), result.is_ok());
+    let tx = match &result {
+        Ok(saved) => saved.tx.as_ref(),
+        Err(e) => e.tx.as_ref(),
+    };
+    if let Some(gas) = tx.and_then(|tx| tx.gas_used) {
+        metrics.count(
+            "da_registry_gas_used_total",
+            &[("da", da.name())],
+            gas as f64,
+        );
+    }
+    result
+}
 
-            let request = RetrieveBlobRequest {
-                batch_header_hash: hash.into(),
-                blob_index: u32::from_le_bytes(index),
-            };
+/// Records the time from storing a blob to its pointer being published, or
+/// to it failing for good.
+fn confirmed(api_context: &ApiContext, da: DA, obj: &Obj, ok: bool) {
+    let took = Duration::from_secs(unix_time().saturating_sub(obj.created_at));
+    api_context.metrics.stage("confirmation", da, took, ok);
+}
+
+/// A DA's answer to a submission.
+enum Submitted {
+    /// Stored; the pointer to publish and the fee paid, when known.
+    Pointer(Vec<u8>, Option<Fee>),
+    /// Accepted by the EigenDA disperser under this request id.
+    Dispersal(Vec<u8>),
+}
+
+/// Sends `data` to `da`, unless the blob's last attempt there has landed.
+/// Each attempt is added to `attempts` before its transaction is sent, so a
+/// retry can check it first.
+async fn submit_to(
+    api_context: &ApiContext,
+    da: DA,
+    data: &[u8],
+    security_params: &[SecurityParam],
+    tenant: Option<&str>,
+    attempts: &Mutex<Vec<Attempt>>,
+) -> Result<Submitted, Failure> {
+    api_context
+        .backends
+        .try_call(da.into(), || async move {
+            if let Some(submitted) = earlier(da, data, attempts).await? {
+                return Ok(submitted);
+            }
+            send(api_context, da, data, security_params, tenant, attempts).await
+        })
+        .await
+}
 
-            let mut client = DisperserClient::connect(EIGEN_SERVER)
+async fn send(
+    api_context: &ApiContext,
+    da: DA,
+    data: &[u8],
+    security_params: &[SecurityParam],
+    tenant: Option<&str>,
+    attempts: &Mutex<Vec<Attempt>>,
+) -> Result<Submitted, Failure> {
+    let record = |attempt| attempts.lock().unwrap().push(attempt);
+    match da {
+        // EigenDA dispersal has no fee to record; the disperser does not charge.
+        DA::EigenDA => {
+            record(Attempt::new(da));
+            eigen::disperse(&api_context.settings.eigenda, data, security_params)
                 .await
-                .map_err(|e| {
-                    Status::internal(format!("Failed to connect to external service: {}", e))
-                })
-                .unwrap();
-
-            // Send the request to the external service
-            let response = client
-                .retrieve_blob(request)
+                .map(Submitted::Dispersal)
+        }
+        DA::Celestia => {
+            let height = celestia::height().await?;
+            record(Attempt {
+                height: Some(height),
+                ..Attempt::new(da)
+            });
+            celestia::submit(&api_context.settings.celestia, data.to_vec())
+                .await
+                .map(|(ptr, fee)| Submitted::Pointer(ptr, Some(fee)))
+        }
+        DA::Avail => avail::submit(data.to_vec(), record)
+            .await
+            .map(|(ptr, fee)| Submitted::Pointer(ptr, fee)),
+        DA::Near => {
+            record(Attempt::new(da));
+            near::submit(api_context.settings.near(tenant), data.to_vec())
                 .await
-                .map_err(|e| {
-                    Status::internal(format!("Failed to send request to external service: {}", e))
-                })
-                .unwrap();
-
-            let response = response.into_inner();
-            String::from_utf8(response.data).unwrap()
-        } else {
-            "Not found".to_string()
+                .map(|(ptr, fee)| Submitted::Pointer(ptr.encode(), fee))
         }
     }
 }
 
-pub(crate) struct MutationRoot;
+fn last_attempt(da: DA, attempts: &Mutex<Vec<Attempt>>) -> Option<Attempt> {
+    attempts
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|attempt| attempt.da == da)
+        .cloned()
+}
 
-fn one_bytes_array<const N: usize>() -> [u8; N] {
-    [1; N]
+/// The pointer left by the blob's last attempt on `da`, if it landed, or
+/// `None` if there was none or it can no longer land. Fails while it may
+/// still land.
+async fn earlier(
+    da: DA,
+    data: &[u8],
+    attempts: &Mutex<Vec<Attempt>>,
+) -> Result<Option<Submitted>, Failure> {
+    let Some(last) = last_attempt(da, attempts) else {
+        return Ok(None);
+    };
+    match landed(data, &last).await? {
+        Landed::Yes(submitted) => Ok(Some(submitted)),
+        Landed::No => Ok(None),
+        Landed::Unknown(e) => Err(Failure::Final(e)),
+    }
+}
+
+/// How often `settle` checks on a submission that may still land.
+const SETTLE_POLL: Duration = Duration::from_secs(10);
+
+/// Settles a submission to `da` that failed with `error` or timed out, and
+/// may have sent its transaction anyway, by watching its last attempt for up
+/// to `wait`. Returns the attempt's result if it landed, a transient failure
+/// if nothing was sent or it was dropped, so the blob can go to the next DA,
+/// and a final one if it may still land.
+async fn settle(
+    da: DA,
+    data: &[u8],
+    attempts: &Mutex<Vec<Attempt>>,
+    wait: Duration,
+    error: String,
+) -> Result<Submitted, Failure> {
+    let Some(last) = last_attempt(da, attempts) else {
+        return Err(Failure::Transient(error));
+    };
+    let deadline = Instant::now() + wait;
+    loop {
+        let pending = match landed(data, &last).await {
+            Ok(Landed::Yes(submitted)) => return Ok(submitted),
+            Ok(Landed::No) => return Err(Failure::Transient(error)),
+            Ok(Landed::Unknown(e)) | Err(Failure::Transient(e)) => e,
+            Err(failure) => {
+                return Err(Failure::Final(format!(
+                    "{}; not trying another DA: {}",
+                    error,
+                    String::from(failure)
+                )))
+            }
+        };
+        if Instant::now() + SETTLE_POLL > deadline {
+            return Err(Failure::Final(format!(
+                "{}; not trying another DA, it may still land: {}",
+                error, pending
+            )));
+        }
+        tokio::time::sleep(SETTLE_POLL).await;
+    }
+}
+
+/// Whether `attempt` made it onto its DA. The fee of an attempt found this
+/// way is not known.
+async fn landed(data: &[u8], attempt: &Attempt) -> Result<Landed<Submitted>, Failure> {
+    let pointer = |ptr| Submitted::Pointer(ptr, None);
+    match attempt.da {
+        DA::Celestia => Ok(celestia::find(data, attempt).await?.map(pointer)),
+        DA::Avail => Ok(avail::find(attempt).await?.map(pointer)),
+        // The blob client only reports the transaction hash once the
+        // transaction is included, so there is nothing to look for. Errors
+        // that leave nothing to land are `Transient` and fail over directly.
+        DA::Near => Err(Failure::Final(
+            "NEAR submission may have been sent; its transaction is not known".to_string(),
+        )),
+        // A dispersal whose request id never came back is never published,
+        // and the disperser does not charge for it.
+        DA::EigenDA => Ok(Landed::No),
+    }
 }
 
+/// Submits a blob recorded under `id` to each of `das` in turn until one
+/// takes it, then publishes the pointer and records the outcome. A DA is
+/// only left for the next one once nothing sent to it can still land; each
+/// DA left is noted in the blob's history. EigenDA blobs stay `PROCESSING`
+/// until `getBlobStatus` sees them confirmed.
+async fn submit_blob(
+    api_context: ApiContext,
+    id: [u8; 32],
+    das: Vec<DA>,
+    security_params: Vec<SecurityParam>,
+    data: Vec<u8>,
+    tenant: Option<String>,
+) {
+    let Some(mut obj) = api_context
+        .map
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .map(Data::into_obj)
+    else {
+        return;
+    };
+
+    let timeout_secs = api_context
+        .settings
+        .failover(tenant.as_deref())
+        .timeout_secs;
+    let mut das = das.into_iter();
+    let Some(mut da) = das.next() else {
+        return;
+    };
+    let attempts = Mutex::new(obj.attempts.clone());
+    let submitted = loop {
+        let started = Instant::now();
+        let submission = submit_to(
+            &api_context,
+            da,
+            &data,
+            &obj.security_params,
+            tenant.as_deref(),
+            &attempts,
+        );
+        let wait = Duration::from_secs(timeout_secs);
+        let metrics = &api_context.metrics;
+        let outcome = match tokio::time::timeout(wait, submission).await {
+            Ok(Err(Failure::Final(e))) => settle(da, &data, &attempts, wait, e).await,
+            Ok(outcome) => outcome,
+            Err(_) => {
+                metrics.error(da.into(), "timeout");
+                let e = format!("{} submission timed out after {}s", da.name(), timeout_secs);
+                settle(da, &data, &attempts, wait, e).await
+            }
+        };
+        obj.attempts = attempts.lock().unwrap().clone();
+        metrics.stage("submission", da, started.elapsed(), outcome.is_ok());
+        if outcome.is_ok() {
+            metrics.count(
+                "da_submitted_bytes_total",
+                &[("da", da.name())],
+                data.len() as f64,
+            );
+        }
+        let error = match outcome {
+            Ok(Submitted::Dispersal(request_id)) => {
+                obj.request_id = request_id;
+                record(&api_context, id, Data::new(da, obj)).await;
+                return;
+            }
+            Ok(Submitted::Pointer(ptr, fee)) => break Ok((ptr, fee)),
+            Err(Failure::Final(e)) => break Err(e),
+            Err(failure) => String::from(failure),
+        };
+
+        let Some(fallback) = das.next() else {
+            break Err(error);
+        };
+        eprintln!(
+            "Submission of {} to {} failed, trying {}: {}",
+            hex::encode(id),
+            da.name(),
+            fallback.name(),
+            error
+        );
+        obj.history.push(StatusChange {
+            status: "FAILED".to_string(),
+            da,
+            error: Some(error),
+            at: unix_time(),
+        });
+        da = fallback;
+        (obj.security_params, obj.codec) = match da {
+            DA::EigenDA => (security_params.clone(), Some(eigen::CODEC_FIELD_ELEMENTS)),
+            _ => (vec![], None),
+        };
+        record(&api_context, id, Data::new(da, obj.clone())).await;
+    };
+
+    let result = match submitted {
+        Ok((ptr, fee)) => {
+            obj.fees.extend(fee);
+            let entry = metadata::wrap(id, &ptr, obj.metadata_hash);
+            obj.pointer = ptr;
+            publish(&api_context, da, entry, None).await
+        }
+        Err(e) => Err(e.into()),
+    };
+    match result {
+        Ok(saved) => {
+            obj.status = "FINALIZED".to_string();
+            obj.op_index = saved.index;
+            obj.fees.extend(Fee::registry(saved.tx.as_ref()));
+            obj.registry_tx = saved.tx;
+        }
+        Err(e) => {
+            obj.status = "FAILED".to_string();
+            obj.error = Some(e.message);
+            obj.fees.extend(Fee::registry(e.tx.as_ref()));
+            obj.registry_tx = e.tx;
+        }
+    }
+    confirmed(&api_context, da, &obj, obj.status != "FAILED");
+    record(&api_context, id, Data::new(da, obj)).await;
+}
+
+/// Saves a background submission's progress. Nothing waits on the task, so
+/// an index that cannot be written is reported in the log; the blob keeps
+/// its last saved state.
+async fn record(api_context: &ApiContext, id: [u8; 32], data: Data) {
+    if let Err(e) = api_context.insert(id, data).await {
+        eprintln!("Could not record blob {}: {}", hex::encode(id), e);
+    }
+}
+
+pub(crate) struct MutationRoot;
+
 #[Object]
 impl MutationRoot {
-    async fn store_blob(&self, ctx: &Context<'_>, data: String, da: DA) -> [u8; 32] {
+    /// Records the blob and submits it to `da` in the background, or to the
+    /// DA the routing policy picks for `AUTO`. The returned id is valid
+    /// immediately for `getBlobStatus` and `getBlobData`, whichever DA is used.
+    async fn store_blob(
+        &self,
+        ctx: &Context<'_>,
+        data: String,
+        da: DaChoice,
+        #[graphql(desc = "EigenDA quorums; defaults to eigenda.security_params from config")]
+        security_params: Option<Vec<SecurityParam>>,
+        #[graphql(desc = "Tags to find the blob by later, e.g. rollup id or batch number")]
+        metadata: Option<Vec<BlobTag>>,
+        #[graphql(
+            desc = "Also commit a hash of the metadata in the registry entry",
+            default = false
+        )]
+        commit_metadata: bool,
+        #[graphql(
+            desc = "DAs to try in order if the submission fails; defaults to the failover config, or the other eligible DAs for AUTO"
+        )]
+        fallback: Option<Vec<DA>>,
+    ) -> async_graphql::Result<StoredBlob> {
         let data = data.into_bytes();
         let api_context = ctx.data_unchecked::<ApiContext>();
+        let tenant = auth::writer(ctx)?;
+        if !api_context.registry.writable() {
+            return Err("This instance is read-only and cannot store blobs".into());
+        }
 
-        match da {
-            DA::Celestia => {
-                let blob = [
-                    TiaBlob::new(TiaNamespace::const_v0(one_bytes_array()), data.clone()).unwrap(),
-                ];
-                let client = Tia::new(TIA_SERVER, Some(TIA_AUTH_TOKEN)).await.unwrap();
-                let height = client
-                    .blob_submit(&blob, SubmitOptions::default())
-                    .await
-                    .unwrap();
-
-                let mut key = concatenate_slices(&height.to_le_bytes(), &blob[0].commitment.0);
-                key.insert(0, DA::Celestia as u8);
-
-                let owner = OPSET_SEED.parse::<LocalWallet>().unwrap();
-                let op_provider = Provider::<Http>::try_from(OPSEP_RPC).unwrap();
-
-                let op_client =
-                    SignerMiddleware::new(op_provider, owner.with_chain_id(11155420u64));
-                let contract_address = OPSEP_CONTRACT.parse::<Address>().unwrap();
-                let contract = MapContract::new(contract_address, Arc::new(&op_client));
-                let tx = contract
-                    .save(key.into())
-                    .send()
-                    .await
-                    .unwrap()
-                    .await
-                    .unwrap()
-                    .unwrap();
-                let op_index: [u8; 32] = tx.logs[0].topics[1].into();
-                let mut map = api_context.map.write().await;
-                map.insert(
-                    op_index,
-                    Data::Celestia(Obj {
-                        status: "FINALIZED".to_string(),
-                        request_id: op_index.into(),
-                        op_index: Some(op_index),
-                    }),
-                );
-                drop(map);
-
-                op_index
-            }
-            DA::Near => {
-                let near_client = Client::new(&Config {
-                    key: KeyType::SecretKey(NEAR_ACCOUNT_ID.to_string(), NEAR_SECRET.to_string()),
-                    network: Network::Testnet,
-                    namespace: Namespace::new(1, 1),
-                    contract: NEAR_ACCOUNT_ID.to_string(),
-                });
-
-                let blobs = [Blob::new_v0(Namespace::new(0, 0), data)];
-                let response = near_client.submit(&blobs).await.unwrap();
-                let mut key: Vec<u8> = CryptoHash::from_str(&response.0).unwrap().0.into();
-
-                let owner = OPSET_SEED.parse::<LocalWallet>().unwrap();
-                let op_provider = Provider::<Http>::try_from(OPSEP_RPC).unwrap();
-
-                let op_client =
-                    SignerMiddleware::new(op_provider, owner.with_chain_id(11155420u64));
-                let contract_address = OPSEP_CONTRACT.parse::<Address>().unwrap();
-                let contract = MapContract::new(contract_address, Arc::new(&op_client));
-                key.insert(0, DA::Near as u8);
-                let tx = contract
-                    .save(key.into())
-                    .send()
-                    .await
-                    .unwrap()
-                    .await
-                    .unwrap()
-                    .unwrap();
-                let op_index: [u8; 32] = tx.logs[0].topics[1].into();
-                let mut map = api_context.map.write().await;
-                map.insert(
-                    op_index,
-                    Data::Near(Obj {
-                        status: "FINALIZED".to_string(),
-                        request_id: op_index.into(),
-                        op_index: Some(op_index),
-                    }),
-                );
-                drop(map);
-
-                op_index // topics[1] is the map index in the contract at which data was stored.
+        let (da, routed, routing) = match da.da() {
+            Some(da) => (da, vec![], None),
+            None => {
+                let (ranked, decision) =
+                    routing::choose(api_context, tenant.as_deref(), data.len() as u64).await?;
+                (ranked[0], ranked[1..].to_vec(), Some(decision))
             }
-            DA::EigenDA => {
-                let request = DisperseBlobRequest {
-                    data,
-                    security_params: vec![SecurityParams {
-                        quorum_id: 0,
-                        adversary_threshold: 25,
-                        quorum_threshold: 50,
-                    }],
-                };
-
-                let mut client = DisperserClient::connect(EIGEN_SERVER)
-                    .await
-                    .map_err(|e| {
-                        Status::internal(format!("Failed to connect to external service: {}", e))
-                    })
-                    .unwrap();
-
-                let response = client
-                    .disperse_blob(request)
-                    .await
-                    .map_err(|e| {
-                        Status::internal(format!(
-                            "Failed to send request to external service: {}",
-                            e
-                        ))
-                    })
-                    .unwrap();
-
-                let response: DisperseBlobReply = response.into_inner();
-                let key = hash_data(&response.request_id);
-                let v = Data::EigenDA(Obj {
-                    status: "Processing".to_owned(),
-                    request_id: response.request_id.clone(),
-                    ..Default::default()
-                });
-                let mut map = api_context.map.write().await;
-                map.insert(key, v);
-                drop(map);
-                key
+        };
+        let fallback = match fallback {
+            Some(fallback) => fallback,
+            None if routing.is_some() => routed,
+            None => api_context
+                .settings
+                .failover(tenant.as_deref())
+                .fallback
+                .iter()
+                .filter_map(|name| DA::from_name(name))
+                .collect(),
+        };
+
+        let eigen_params =
+            security_params.unwrap_or_else(|| api_context.settings.eigenda.security_params.clone());
+        if da == DA::EigenDA || fallback.contains(&DA::EigenDA) {
+            eigen::validate(&eigen_params)?;
+        }
+        let security_params = match da {
+            DA::EigenDA => eigen_params.clone(),
+            _ => vec![],
+        };
+
+        let metadata = metadata::collect(metadata.unwrap_or_default())?;
+        let metadata_hash = commit_metadata.then(|| metadata::hash(&metadata));
+
+        let (in_flight, admission) = api_context
+            .quotas
+            .admit(api_context, tenant.as_deref(), da, data.len() as u64)
+            .await?;
+        let id = api_context.new_blob_id(&data).await;
+        let obj = Obj {
+            status: "PROCESSING".to_owned(),
+            content_hash: Some(hash_data(&data)),
+            size: data.len() as u64,
+            tenant: tenant.clone(),
+            metadata,
+            metadata_hash,
+            security_params,
+            codec: (da == DA::EigenDA).then_some(eigen::CODEC_FIELD_ELEMENTS),
+            routing: routing.clone(),
+            ..Default::default()
+        };
+        api_context.insert(id, Data::new(da, obj)).await?;
+        drop(admission);
+
+        // Each DA is tried at most once, and only if its own quota has room.
+        let mut das = vec![da];
+        for fallback in fallback {
+            if das.contains(&fallback) {
+                continue;
             }
-            DA::Avail => {
-                let client = build_client(AVAIL_SERVER, true).await.unwrap();
-                let pair = Pair::from_string_with_seed(AVAIL_SEED, None).unwrap();
-                let signer = PairSigner::new(pair.0);
-
-                let data_transfer = api::tx()
-                    .data_availability()
-                    .submit_data(BoundedVec(data.clone()));
-                let extrinsic_params = AvailExtrinsicParams::new_with_app_id(1.into());
-
-                let h = client
-                    .tx()
-                    .sign_and_submit_then_watch(&data_transfer, &signer, extrinsic_params)
-                    .await
-                    .unwrap()
-                    .wait_for_finalized_success()
-                    .await
-                    .unwrap();
-
-                // let mut map = api_context.map.write().await;
-                let block_hash = h.block_hash();
-                let hash = block_hash.as_bytes();
-                let index = h.extrinsic_index().to_le_bytes();
-                let mut key = concatenate_slices(hash, &index);
-
-                let owner = OPSET_SEED.parse::<LocalWallet>().unwrap();
-                let op_provider = Provider::<Http>::try_from(OPSEP_RPC).unwrap();
-
-                let op_client =
-                    SignerMiddleware::new(op_provider, owner.with_chain_id(11155420u64));
-                let contract_address = OPSEP_CONTRACT.parse::<Address>().unwrap();
-                let contract = MapContract::new(contract_address, Arc::new(op_client));
-                key.insert(0, DA::Avail as u8);
-                let tx = contract
-                    .save(key.into())
-                    .send()
-                    .await
-                    .unwrap()
-                    .await
-                    .unwrap()
-                    .unwrap();
-
-                let op_index: [u8; 32] = tx.logs[0].topics[1].into();
-                let mut map = api_context.map.write().await;
-                map.insert(
-                    op_index,
-                    Data::Avail(Obj {
-                        status: "FINALIZED".to_string(),
-                        request_id: op_index.into(),
-                        op_index: Some(op_index),
-                    }),
-                );
-                drop(map);
-
-                tx.logs[0].topics[1].into() // topics[1] is the map index in the contract at which data was stored.            }
+            let allowed = api_context
+                .quotas
+                .allows_fallback(api_context, tenant.as_deref(), fallback, data.len() as u64)
+                .await;
+            if allowed.is_ok() {
+                das.push(fallback);
             }
         }
+
+        let api_context = api_context.clone();
+        tokio::spawn(async move {
+            submit_blob(api_context, id, das, eigen_params, data, tenant).await;
+            drop(in_flight);
+        });
+        Ok(StoredBlob { id, da, routing })
     }
 }
@@ -0,0 +1,448 @@
+use std::str::FromStr;
+
+use near_da_rpc::{
+    near::{
+        config::{Config, KeyType, Network},
+        Client,
+    },
+    Blob, CryptoHash, DataAvailability, Namespace,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    billing::Fee,
+    config::{NearNetwork, NearSettings},
+    model::DA,
+    near_proof,
+    retry::Failure,
+};
+
+// Gas for a `submit` function call to the blob contract, from the protocol
+// fee schedule: each cost is charged once to send and once to execute.
+const RECEIPT_GAS: u64 = 2 * 108_059_500_000;
+const FUNCTION_CALL_GAS: u64 = 2 * 2_319_861_500_000;
+const FUNCTION_CALL_BYTE_GAS: u64 = 2 * 2_235_934;
+/// Allowance for running the contract's `submit`, which stores nothing.
+const SUBMIT_EXECUTION_GAS: u64 = 5_000_000_000_000;
+/// Borsh framing around the data in the call arguments.
+const SUBMIT_ARGS_OVERHEAD: u64 = 64;
+
+const TESTNET: u8 = 0;
+const MAINNET: u8 = 1;
+const CUSTOM: u8 = 2;
+
+/// Everything needed to read a NEAR blob back, as stored in the registry.
+///
+/// Layout after the `DA::Near` tag byte:
+/// `tx_hash[32] | ns_version[1] | ns_id[4 LE] | network[1] | contract_len[1] | contract
+/// | (rpc_len[1] | rpc, custom networks only) | signer_len[1] | signer`.
+/// Pointers written before this layout carry only the tx hash and are read with
+/// the deployment defaults.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NearPointer {
+    pub(crate) tx_hash: [u8; 32],
+    pub(crate) namespace_version: u8,
+    pub(crate) namespace_id: u32,
+    pub(crate) network: NearNetwork,
+    pub(crate) contract: String,
+    /// Account that signed the submission; light-client proofs are keyed by it.
+    pub(crate) signer: String,
+}
+
+/// Checks that the fields a pointer records fit its one-byte length prefixes.
+pub(crate) fn validate(settings: &NearSettings) -> Result<(), String> {
+    let rpc = match &settings.network {
+        NearNetwork::Custom(rpc) => Some(("network", rpc)),
+        _ => None,
+    };
+    for (name, value) in [
+        ("contract", &settings.contract),
+        ("account_id", &settings.account_id),
+    ]
+    .into_iter()
+    .chain(rpc)
+    {
+        if value.len() > u8::MAX as usize {
+            return Err(format!(
+                "{} is {} bytes, at most {} fit in a pointer",
+                name,
+                value.len(),
+                u8::MAX
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl NearPointer {
+    /// Lengths fit in a byte for settings that passed `validate`.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut ptr = vec![DA::Near as u8];
+        ptr.extend_from_slice(&self.tx_hash);
+        ptr.push(self.namespace_version);
+        ptr.extend_from_slice(&self.namespace_id.to_le_bytes());
+        match &self.network {
+            NearNetwork::Testnet => ptr.push(TESTNET),
+            NearNetwork::Mainnet => ptr.push(MAINNET),
+            NearNetwork::Custom(_) => ptr.push(CUSTOM),
+        }
+        ptr.push(self.contract.len() as u8);
+        ptr.extend_from_slice(self.contract.as_bytes());
+        if let NearNetwork::Custom(rpc) = &self.network {
+            ptr.push(rpc.len() as u8);
+            ptr.extend_from_slice(rpc.as_bytes());
+        }
+        ptr.push(self.signer.len() as u8);
+        ptr.extend_from_slice(self.signer.as_bytes());
+        ptr
+    }
+
+    /// Decodes a pointer including its `DA::Near` tag byte.
+    pub(crate) fn decode(ptr: &[u8], defaults: &NearSettings) -> Option<Self> {
+        if ptr.first() != Some(&(DA::Near as u8)) || ptr.len() < 33 {
+            return None;
+        }
+        let tx_hash: [u8; 32] = ptr[1..33].try_into().ok()?;
+        if ptr.len() == 33 {
+            return Some(NearPointer {
+                tx_hash,
+                namespace_version: defaults.namespace_version,
+                namespace_id: defaults.namespace_id,
+                network: defaults.network.clone(),
+                contract: defaults.contract.clone(),
+                signer: defaults.account_id.clone(),
+            });
+        }
+
+        let namespace_version = *ptr.get(33)?;
+        let namespace_id = u32::from_le_bytes(ptr.get(34..38)?.try_into().ok()?);
+        let network_tag = *ptr.get(38)?;
+        let contract_len = *ptr.get(39)? as usize;
+        let contract = String::from_utf8(ptr.get(40..40 + contract_len)?.to_vec()).ok()?;
+        let mut rest = &ptr[40 + contract_len..];
+        let network = match network_tag {
+            TESTNET => NearNetwork::Testnet,
+            MAINNET => NearNetwork::Mainnet,
+            CUSTOM => {
+                let rpc_len = *rest.first()? as usize;
+                let rpc = String::from_utf8(rest.get(1..1 + rpc_len)?.to_vec()).ok()?;
+                rest = &rest[1 + rpc_len..];
+                NearNetwork::Custom(rpc)
+            }
+            _ => return None,
+        };
+        let signer_len = *rest.first()? as usize;
+        let signer = String::from_utf8(rest.get(1..1 + signer_len)?.to_vec()).ok()?;
+
+        Some(NearPointer {
+            tx_hash,
+            namespace_version,
+            namespace_id,
+            network,
+            contract,
+            signer,
+        })
+    }
+}
+
+/// JSON-RPC endpoint for `network`, used for calls the blob client does not cover.
+pub(crate) fn rpc_url(network: &NearNetwork) -> &str {
+    match network {
+        NearNetwork::Testnet => "https://rpc.testnet.near.org",
+        NearNetwork::Mainnet => "https://rpc.mainnet.near.org",
+        NearNetwork::Custom(rpc) => rpc,
+    }
+}
+
+fn network(network: &NearNetwork) -> Network {
+    match network {
+        NearNetwork::Testnet => Network::Testnet,
+        NearNetwork::Mainnet => Network::Mainnet,
+        NearNetwork::Custom(rpc) => Network::Custom(rpc.clone()),
+    }
+}
+
+pub(crate) fn client(
+    signer: &NearSettings,
+    contract: &str,
+    net: &NearNetwork,
+    namespace: Namespace,
+) -> Client {
+    Client::new(&Config {
+        key: KeyType::SecretKey(signer.account_id.clone(), signer.secret.clone()),
+        network: network(net),
+        namespace,
+        contract: contract.to_string(),
+    })
+}
+
+/// Errors from the blob client after which the transaction cannot be
+/// included: it never reached the RPC node (the access key lookup failed, or
+/// the connection could not be made) or the node rejected it without
+/// forwarding it. Matched on the error text since the client reports errors
+/// as strings.
+const UNSENT: [&str; 9] = [
+    "InvalidTransaction",
+    "InvalidTxError",
+    "InvalidNonce",
+    "NotEnoughBalance",
+    "InvalidAccessKeyError",
+    "UnknownAccessKey",
+    "UnknownAccount",
+    "Connection refused",
+    "dns error",
+];
+
+/// Whether a failed submission left nothing that can still land. Timeouts,
+/// and errors not known to be one of `UNSENT`, may still be included.
+fn unsent(error: &str) -> bool {
+    !error.contains("Timeout") && UNSENT.iter().any(|marker| error.contains(marker))
+}
+
+/// Submits `data` to the blob contract configured in `settings` and returns the
+/// pointer to store in the registry and the tokens burnt for the transaction.
+pub(crate) async fn submit(
+    settings: &NearSettings,
+    data: Vec<u8>,
+) -> Result<(NearPointer, Option<Fee>), Failure> {
+    let namespace = || Namespace::new(settings.namespace_version, settings.namespace_id);
+    let near_client = client(settings, &settings.contract, &settings.network, namespace());
+
+    let blobs = [Blob::new_v0(namespace(), data)];
+    let response = near_client.submit(&blobs).await.map_err(|e| {
+        let message = format!("NEAR submission failed: {:#}", e);
+        if unsent(&message) {
+            Failure::Transient(message)
+        } else {
+            Failure::Final(message)
+        }
+    })?;
+
+    let ptr = NearPointer {
+        tx_hash: CryptoHash::from_str(&response.0)
+            .map_err(|e| {
+                Failure::Final(format!("NEAR returned an invalid transaction hash: {}", e))
+            })?
+            .0,
+        namespace_version: settings.namespace_version,
+        namespace_id: settings.namespace_id,
+        network: settings.network.clone(),
+        contract: settings.contract.clone(),
+        signer: settings.account_id.clone(),
+    };
+    let fee = match tokens_burnt(&ptr).await {
+        Ok(burnt) => Some(Fee::da(burnt, "yoctoNEAR")),
+        Err(e) => {
+            eprintln!("Could not read NEAR transaction fee: {}", e);
+            None
+        }
+    };
+    Ok((ptr, fee))
+}
+
+/// Gas a `submit` of `size` bytes is expected to burn.
+fn gas(size: u64) -> u64 {
+    RECEIPT_GAS
+        + FUNCTION_CALL_GAS
+        + FUNCTION_CALL_BYTE_GAS * (size + SUBMIT_ARGS_OVERHEAD)
+        + SUBMIT_EXECUTION_GAS
+}
+
+/// Expected yoctoNEAR burnt for submitting `size` bytes on `network`: the gas
+/// model above at the current gas price.
+pub(crate) async fn estimate(network: &NearNetwork, size: u64) -> Result<u128, String> {
+    let price = near_proof::rpc(rpc_url(network), "gas_price", json!([null])).await?;
+    let price: u128 = price["gas_price"]
+        .as_str()
+        .and_then(|price| price.parse().ok())
+        .ok_or("NEAR RPC returned no gas price")?;
+    Ok(u128::from(gas(size)) * price)
+}
+
+/// yoctoNEAR held by `settings`' account.
+pub(crate) async fn balance(settings: &NearSettings) -> Result<u128, String> {
+    let account = near_proof::rpc(
+        rpc_url(&settings.network),
+        "query",
+        json!({
+            "request_type": "view_account",
+            "finality": "final",
+            "account_id": settings.account_id,
+        }),
+    )
+    .await?;
+    account["amount"]
+        .as_str()
+        .and_then(|amount| amount.parse().ok())
+        .ok_or_else(|| "NEAR RPC returned no account balance".to_string())
+}
+
+/// Latest block height known to `network`'s RPC node. Fails while the node
+/// is still syncing.
+pub(crate) async fn rpc_status(network: &NearNetwork) -> Result<u64, String> {
+    let status = near_proof::rpc(rpc_url(network), "status", json!([])).await?;
+    let sync_info = &status["sync_info"];
+    if sync_info["syncing"].as_bool() == Some(true) {
+        return Err("NEAR RPC node is syncing".to_string());
+    }
+    sync_info["latest_block_height"]
+        .as_u64()
+        .ok_or_else(|| "NEAR RPC returned no block height".to_string())
+}
+
+/// yoctoNEAR burnt by the submission transaction and the receipts it spawned.
+async fn tokens_burnt(ptr: &NearPointer) -> Result<u128, String> {
+    let tx_hash = bs58::encode(ptr.tx_hash).into_string();
+    let status = near_proof::rpc(rpc_url(&ptr.network), "tx", json!([tx_hash, ptr.signer])).await?;
+
+    let burnt = |outcome: &Value| -> Result<u128, String> {
+        outcome["outcome"]["tokens_burnt"]
+            .as_str()
+            .and_then(|burnt| burnt.parse().ok())
+            .ok_or_else(|| "Transaction outcome has no tokens_burnt".to_string())
+    };
+    let mut total = burnt(&status["transaction_outcome"])?;
+    for receipt in status["receipts_outcome"].as_array().into_iter().flatten() {
+        total += burnt(receipt)?;
+    }
+    Ok(total)
+}
+
+/// Reads a blob back using the contract, network and namespace recorded in its
+/// pointer. Reads are not signed, so the default account is used as the key.
+pub(crate) async fn get(ptr: &NearPointer, defaults: &NearSettings) -> Result<Vec<u8>, Failure> {
+    let namespace = Namespace::new(ptr.namespace_version, ptr.namespace_id);
+    let near_client = client(defaults, &ptr.contract, &ptr.network, namespace);
+
+    near_client
+        .get(CryptoHash(ptr.tx_hash))
+        .await
+        .map(|blob| blob.0.data)
+        .map_err(|e| Failure::Transient(format!("NEAR retrieval failed: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pointer(network: NearNetwork) -> NearPointer {
+        NearPointer {
+            tx_hash: [7; 32],
+            namespace_version: 1,
+            namespace_id: 0x0102_0304,
+            network,
+            contract: "blobs.rollup-a.near".to_string(),
+            signer: "rollup-a.near".to_string(),
+        }
+    }
+
+    fn settings(network: NearNetwork) -> NearSettings {
+        NearSettings {
+            network,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for network in [
+            NearNetwork::Testnet,
+            NearNetwork::Mainnet,
+            NearNetwork::Custom("https://rpc.example.com".to_string()),
+        ] {
+            let ptr = pointer(network);
+            let decoded = NearPointer::decode(&ptr.encode(), &NearSettings::default());
+            assert_eq!(decoded, Some(ptr));
+        }
+    }
+
+    #[test]
+    fn reads_legacy_pointer_with_defaults() {
+        let mut legacy = vec![DA::Near as u8];
+        legacy.extend_from_slice(&[7; 32]);
+        let defaults = NearSettings::default();
+        let ptr = NearPointer::decode(&legacy, &defaults).unwrap();
+        assert_eq!(ptr.tx_hash, [7; 32]);
+        assert_eq!(ptr.contract, defaults.contract);
+        assert_eq!(ptr.signer, defaults.account_id);
+    }
+
+    #[test]
+    fn round_trips_fields_at_the_length_limit() {
+        let longest = "a".repeat(255);
+        let ptr = NearPointer {
+            contract: longest.clone(),
+            signer: longest.clone(),
+            ..pointer(NearNetwork::Custom(longest.clone()))
+        };
+        let decoded = NearPointer::decode(&ptr.encode(), &NearSettings::default());
+        assert_eq!(decoded, Some(ptr));
+
+        let settings = NearSettings {
+            account_id: longest.clone(),
+            contract: longest.clone(),
+            ..settings(NearNetwork::Custom(longest))
+        };
+        assert!(validate(&settings).is_ok());
+    }
+
+    #[test]
+    fn rejects_fields_over_the_length_limit() {
+        let long = "a".repeat(256);
+        let contract = NearSettings {
+            contract: long.clone(),
+            ..settings(NearNetwork::Testnet)
+        };
+        assert!(validate(&contract).is_err());
+        let account = NearSettings {
+            account_id: long.clone(),
+            ..settings(NearNetwork::Testnet)
+        };
+        assert!(validate(&account).is_err());
+        assert!(validate(&settings(NearNetwork::Custom(long))).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_pointers() {
+        let defaults = NearSettings::default();
+        let encoded = pointer(NearNetwork::Custom("https://rpc.example.com".to_string())).encode();
+        // 33 bytes is a legacy pointer; anything shorter or cut off later in
+        // the layout is not a pointer.
+        for len in (0..33).chain(34..encoded.len()) {
+            assert!(
+                NearPointer::decode(&encoded[..len], &defaults).is_none(),
+                "decoded {} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_das_and_unknown_networks() {
+        let defaults = NearSettings::default();
+        let mut encoded = pointer(NearNetwork::Testnet).encode();
+        encoded[38] = 9;
+        assert!(NearPointer::decode(&encoded, &defaults).is_none());
+        encoded[0] = DA::Celestia as u8;
+        assert!(NearPointer::decode(&encoded, &defaults).is_none());
+    }
+
+    #[test]
+    fn classifies_submission_errors() {
+        for unsent_error in [
+            "NEAR submission failed: InvalidTxError(InvalidNonce { tx_nonce: 4, ak_nonce: 5 })",
+            "NEAR submission failed: NotEnoughBalance { signer_id: \"rollup-a.near\" }",
+            "NEAR submission failed: error sending request: Connection refused (os error 111)",
+        ] {
+            assert!(unsent(unsent_error), "{}", unsent_error);
+        }
+        for final_error in [
+            "NEAR submission failed: Timeout",
+            "NEAR submission failed: InvalidTransaction after Timeout",
+            "NEAR submission failed: ActionError(FunctionCallError)",
+        ] {
+            assert!(!unsent(final_error), "{}", final_error);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{hash_data, near::rpc_url, near::NearPointer};
+
+type ProofResult<T> = Result<T, String>;
+
+/// Fetches the light-client execution outcome proof for the transaction that
+/// submitted the blob behind `ptr`, against `head` (a block hash known to the
+/// verifying light client) or the latest final block when `head` is `None`.
+///
+/// Returns the raw RPC proof together with the `block_merkle_root` of the head
+/// the proof was built against.
+pub(crate) async fn fetch(ptr: &NearPointer, head: Option<String>) -> ProofResult<(Value, String)> {
+    let url = rpc_url(&ptr.network);
+    let head = match head {
+        Some(hash) => rpc(url, "block", json!({ "block_id": hash })).await?,
+        None => rpc(url, "block", json!({ "finality": "final" })).await?,
+    };
+    let head_hash = head["header"]["hash"]
+        .as_str()
+        .ok_or("Light client head has no hash")?
+        .to_string();
+    let block_merkle_root = head["header"]["block_merkle_root"]
+        .as_str()
+        .ok_or("Light client head has no block_merkle_root")?
+        .to_string();
+
+    let proof = rpc(
+        url,
+        "EXPERIMENTAL_light_client_proof",
+        json!({
+            "type": "transaction",
+            "transaction_hash": bs58::encode(ptr.tx_hash).into_string(),
+            "sender_id": ptr.signer,
+            "light_client_head": head_hash,
+        }),
+    )
+    .await?;
+
+    Ok((proof, block_merkle_root))
+}
+
+pub(crate) async fn rpc(url: &str, method: &str, params: Value) -> ProofResult<Value> {
+    let response: Value = reqwest::Client::new()
+        .post(url)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": "da",
+            "method": method,
+            "params": params,
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("NEAR RPC request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("NEAR RPC returned invalid JSON: {}", e))?;
+
+    match response.get("error") {
+        Some(err) => Err(format!("NEAR RPC error: {}", err)),
+        None => Ok(response["result"].clone()),
+    }
+}
+
+#[derive(Deserialize)]
+struct LightClientProof {
+    outcome_proof: OutcomeProof,
+    outcome_root_proof: Vec<MerklePathItem>,
+    block_header_lite: BlockHeaderLite,
+    block_proof: Vec<MerklePathItem>,
+}
+
+#[derive(Deserialize)]
+struct OutcomeProof {
+    proof: Vec<MerklePathItem>,
+    block_hash: String,
+    id: String,
+    outcome: Outcome,
+}
+
+#[derive(Deserialize)]
+struct Outcome {
+    logs: Vec<String>,
+    receipt_ids: Vec<String>,
+    gas_burnt: u64,
+    tokens_burnt: String,
+    executor_id: String,
+    status: Value,
+}
+
+#[derive(Deserialize)]
+struct MerklePathItem {
+    hash: String,
+    direction: Direction,
+}
+
+#[derive(Deserialize)]
+enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Deserialize)]
+struct BlockHeaderLite {
+    prev_block_hash: String,
+    inner_rest_hash: String,
+    inner_lite: InnerLite,
+}
+
+#[derive(Deserialize)]
+struct InnerLite {
+    height: u64,
+    epoch_id: String,
+    next_epoch_id: String,
+    prev_state_root: String,
+    outcome_root: String,
+    timestamp_nanosec: String,
+    next_bp_hash: String,
+    block_merkle_root: String,
+}
+
+/// Verifies a proof returned by [`fetch`] the same way a NEAR light client
+/// does: the outcome must hash up to the block's outcome root, the block must
+/// hash up to `block_merkle_root` of the light client head, and the outcome must
+/// belong to `tx_hash`.
+pub(crate) fn verify(
+    proof: &Value,
+    tx_hash: &[u8; 32],
+    block_merkle_root: &str,
+) -> ProofResult<()> {
+    let proof: LightClientProof = serde_json::from_value(proof.clone())
+        .map_err(|e| format!("Malformed light client proof: {}", e))?;
+
+    if decode_hash(&proof.outcome_proof.id)? != *tx_hash {
+        return Err("Proof is for a different transaction".to_string());
+    }
+
+    let outcome_hash = hash_data(&borsh_hashes(&outcome_hashes(&proof.outcome_proof)?));
+    let shard_outcome_root = compute_root(&proof.outcome_proof.proof, outcome_hash)?;
+    let block_outcome_root =
+        compute_root(&proof.outcome_root_proof, hash_data(&shard_outcome_root))?;
+
+    let header = &proof.block_header_lite;
+    if block_outcome_root != decode_hash(&header.inner_lite.outcome_root)? {
+        return Err("Outcome is not included in the block's outcome root".to_string());
+    }
+
+    let block_hash = block_hash(header)?;
+    if block_hash != decode_hash(&proof.outcome_proof.block_hash)? {
+        return Err("Block header does not match the outcome's block".to_string());
+    }
+    if compute_root(&proof.block_proof, block_hash)? != decode_hash(block_merkle_root)? {
+        return Err(
+            "Block is not included in the light client head's block merkle root".to_string(),
+        );
+    }
+
+    Ok(())
+}
+
+/// `ExecutionOutcomeWithId::to_hashes`: the id, the hash of the borsh-encoded
+/// partial outcome, then the hash of every log line.
+fn outcome_hashes(proof: &OutcomeProof) -> ProofResult<Vec<[u8; 32]>> {
+    let outcome = &proof.outcome;
+    let mut partial = Vec::new();
+    partial.extend_from_slice(&borsh_hashes(
+        &outcome
+            .receipt_ids
+            .iter()
+            .map(|id| decode_hash(id))
+            .collect::<ProofResult<Vec<_>>>()?,
+    ));
+    partial.extend_from_slice(&outcome.gas_burnt.to_le_bytes());
+    let tokens_burnt: u128 = outcome
+        .tokens_burnt
+        .parse()
+        .map_err(|_| "Invalid tokens_burnt".to_string())?;
+    partial.extend_from_slice(&tokens_burnt.to_le_bytes());
+    borsh_bytes(&mut partial, outcome.executor_id.as_bytes());
+    partial.extend_from_slice(&partial_status(&outcome.status)?);
+
+    let mut hashes = vec![decode_hash(&proof.id)?, hash_data(&partial)];
+    hashes.extend(outcome.logs.iter().map(|log| hash_data(log.as_bytes())));
+    Ok(hashes)
+}
+
+/// Borsh encoding of `PartialExecutionStatus`; failures are committed to
+/// without their error payload.
+fn partial_status(status: &Value) -> ProofResult<Vec<u8>> {
+    if status.as_str() == Some("Unknown") {
+        return Ok(vec![0]);
+    }
+    if status.get("Failure").is_some() {
+        return Ok(vec![1]);
+    }
+    if let Some(value) = status.get("SuccessValue").and_then(Value::as_str) {
+        let value = STANDARD
+            .decode(value)
+            .map_err(|_| "Invalid SuccessValue".to_string())?;
+        let mut encoded = vec![2];
+        borsh_bytes(&mut encoded, &value);
+        return Ok(encoded);
+    }
+    if let Some(receipt) = status.get("SuccessReceiptId").and_then(Value::as_str) {
+        let mut encoded = vec![3];
+        encoded.extend_from_slice(&decode_hash(receipt)?);
+        return Ok(encoded);
+    }
+    Err(format!("Unsupported outcome status: {}", status))
+}
+
+/// `BlockHeader::compute_hash` from the lite header.
+fn block_hash(header: &BlockHeaderLite) -> ProofResult<[u8; 32]> {
+    let inner = &header.inner_lite;
+    let timestamp: u64 = inner
+        .timestamp_nanosec
+        .parse()
+        .map_err(|_| "Invalid timestamp_nanosec".to_string())?;
+
+    let mut inner_lite = Vec::with_capacity(208);
+    inner_lite.extend_from_slice(&inner.height.to_le_bytes());
+    inner_lite.extend_from_slice(&decode_hash(&inner.epoch_id)?);
+    inner_lite.extend_from_slice(&decode_hash(&inner.next_epoch_id)?);
+    inner_lite.extend_from_slice(&decode_hash(&inner.prev_state_root)?);
+    inner_lite.extend_from_slice(&decode_hash(&inner.outcome_root)?);
+    inner_lite.extend_from_slice(&timestamp.to_le_bytes());
+    inner_lite.extend_from_slice(&decode_hash(&inner.next_bp_hash)?);
+    inner_lite.extend_from_slice(&decode_hash(&inner.block_merkle_root)?);
+
+    let inner_hash = combine(
+        &hash_data(&inner_lite),
+        &decode_hash(&header.inner_rest_hash)?,
+    );
+    Ok(combine(&inner_hash, &decode_hash(&header.prev_block_hash)?))
+}
+
+fn compute_root(path: &[MerklePathItem], item: [u8; 32]) -> ProofResult<[u8; 32]> {
+    path.iter().try_fold(item, |acc, step| {
+        let hash = decode_hash(&step.hash)?;
+        Ok(match step.direction {
+            Direction::Left => combine(&hash, &acc),
+            Direction::Right => combine(&acc, &hash),
+        })
+    })
+}
+
+fn combine(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut both = [0u8; 64];
+    both[..32].copy_from_slice(left);
+    both[32..].copy_from_slice(right);
+    hash_data(&both)
+}
+
+fn borsh_hashes(hashes: &[[u8; 32]]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(4 + hashes.len() * 32);
+    encoded.extend_from_slice(&(hashes.len() as u32).to_le_bytes());
+    hashes.iter().for_each(|h| encoded.extend_from_slice(h));
+    encoded
+}
+
+fn borsh_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn decode_hash(hash: &str) -> ProofResult<[u8; 32]> {
+    bs58::decode(hash)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid hash {}", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn b58(hash: &[u8; 32]) -> String {
+        bs58::encode(hash).into_string()
+    }
+
+    fn step(hash: &[u8; 32], direction: &str) -> Value {
+        json!({ "hash": b58(hash), "direction": direction })
+    }
+
+    fn pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        hash_data(&[&left[..], &right[..]].concat())
+    }
+
+    const TX: [u8; 32] = [1; 32];
+    const RECEIPT: [u8; 32] = [2; 32];
+
+    /// A proof for a transaction outcome that is the second of four in its
+    /// shard, in the first of two shards, in a block one step below the head.
+    /// Hashes are computed here from nearcore's borsh layouts rather than
+    /// with the verifier's own helpers. Returns the proof and the head's
+    /// `block_merkle_root`.
+    fn fixture() -> (Value, [u8; 32]) {
+        let mut partial = vec![];
+        partial.extend_from_slice(&1u32.to_le_bytes());
+        partial.extend_from_slice(&RECEIPT);
+        partial.extend_from_slice(&2_428_000_000_000u64.to_le_bytes());
+        partial.extend_from_slice(&242_800_000_000_000_000_000u128.to_le_bytes());
+        partial.extend_from_slice(&12u32.to_le_bytes());
+        partial.extend_from_slice(b"blob.testnet");
+        partial.push(3);
+        partial.extend_from_slice(&RECEIPT);
+        let mut hashes = vec![];
+        hashes.extend_from_slice(&2u32.to_le_bytes());
+        hashes.extend_from_slice(&TX);
+        hashes.extend_from_slice(&hash_data(&partial));
+        let outcome_hash = hash_data(&hashes);
+
+        let (first, third, fourth) = ([10; 32], [11; 32], [12; 32]);
+        let shard_root = pair(&pair(&first, &outcome_hash), &pair(&third, &fourth));
+        let other_shard = [13; 32];
+        let outcome_root = pair(&hash_data(&shard_root), &other_shard);
+
+        let (epoch, next_epoch, state_root, next_bp) = ([20; 32], [21; 32], [22; 32], [23; 32]);
+        let (merkle_root, rest, prev) = ([24; 32], [25; 32], [26; 32]);
+        let mut inner_lite = vec![];
+        inner_lite.extend_from_slice(&150_000_000u64.to_le_bytes());
+        for hash in [&epoch, &next_epoch, &state_root, &outcome_root] {
+            inner_lite.extend_from_slice(hash);
+        }
+        inner_lite.extend_from_slice(&1_700_000_000_000_000_000u64.to_le_bytes());
+        inner_lite.extend_from_slice(&next_bp);
+        inner_lite.extend_from_slice(&merkle_root);
+        let block = pair(&pair(&hash_data(&inner_lite), &rest), &prev);
+
+        let sibling = [30; 32];
+        let head_root = pair(&sibling, &block);
+
+        let proof = json!({
+            "outcome_proof": {
+                "proof": [step(&first, "Left"), step(&pair(&third, &fourth), "Right")],
+                "block_hash": b58(&block),
+                "id": b58(&TX),
+                "outcome": {
+                    "logs": [],
+                    "receipt_ids": [b58(&RECEIPT)],
+                    "gas_burnt": 2_428_000_000_000u64,
+                    "tokens_burnt": "242800000000000000000",
+                    "executor_id": "blob.testnet",
+                    "status": { "SuccessReceiptId": b58(&RECEIPT) },
+                },
+            },
+            "outcome_root_proof": [step(&other_shard, "Right")],
+            "block_header_lite": {
+                "prev_block_hash": b58(&prev),
+                "inner_rest_hash": b58(&rest),
+                "inner_lite": {
+                    "height": 150_000_000u64,
+                    "epoch_id": b58(&epoch),
+                    "next_epoch_id": b58(&next_epoch),
+                    "prev_state_root": b58(&state_root),
+                    "outcome_root": b58(&outcome_root),
+                    "timestamp_nanosec": "1700000000000000000",
+                    "next_bp_hash": b58(&next_bp),
+                    "block_merkle_root": b58(&merkle_root),
+                },
+            },
+            "block_proof": [step(&sibling, "Left")],
+        });
+        (proof, head_root)
+    }
+
+    #[test]
+    fn verifies_included_outcome() {
+        let (proof, root) = fixture();
+        verify(&proof, &TX, &b58(&root)).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_transaction() {
+        let (proof, root) = fixture();
+        assert!(verify(&proof, &[9; 32], &b58(&root)).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_outcome() {
+        let (mut proof, root) = fixture();
+        proof["outcome_proof"]["outcome"]["tokens_burnt"] = json!("1");
+        assert!(verify(&proof, &TX, &b58(&root)).is_err());
+
+        let (mut proof, root) = fixture();
+        proof["outcome_proof"]["outcome"]["logs"] = json!(["forged"]);
+        assert!(verify(&proof, &TX, &b58(&root)).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_path() {
+        let (mut proof, root) = fixture();
+        proof["outcome_proof"]["proof"][0] = step(&[14; 32], "Left");
+        assert!(verify(&proof, &TX, &b58(&root)).is_err());
+
+        let (mut proof, root) = fixture();
+        proof["block_proof"][0]["direction"] = json!("Right");
+        assert!(verify(&proof, &TX, &b58(&root)).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_root() {
+        let (proof, mut root) = fixture();
+        root[0] ^= 1;
+        assert!(verify(&proof, &TX, &b58(&root)).is_err());
+    }
+}
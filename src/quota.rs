@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_graphql::SimpleObject;
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+
+use crate::{config::Limits, unix_time, ApiContext, DA};
+
+const MINUTE: u64 = 60;
+const DAY: u64 = 86_400;
+
+type InFlightCounts = Arc<Mutex<HashMap<(Option<String>, u8), u64>>>;
+
+/// Enforces the `quota` settings on `storeBlob`. Blob and byte counts are
+/// read from the index, so they survive a restart; submissions in flight are
+/// counted here, since their background tasks do not.
+#[derive(Default)]
+pub(crate) struct Quotas {
+    /// Held from the check until the new blob is recorded, so concurrent
+    /// requests cannot both slip under a limit.
+    admission: AsyncMutex<()>,
+    in_flight: InFlightCounts,
+}
+
+/// Counts a submission against `max_in_flight` until dropped.
+pub(crate) struct InFlight {
+    counts: InFlightCounts,
+    key: (Option<String>, u8),
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(count) = self.counts.lock().unwrap().get_mut(&self.key) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+/// How much of a limit is used up.
+#[derive(SimpleObject)]
+pub(crate) struct QuotaCounter {
+    limit: u64,
+    used: u64,
+    remaining: u64,
+}
+
+impl QuotaCounter {
+    fn new(limit: Option<u64>, used: u64) -> Option<Self> {
+        limit.map(|limit| QuotaCounter {
+            limit,
+            used,
+            remaining: limit.saturating_sub(used),
+        })
+    }
+}
+
+/// A tenant's limits and current usage, across all DAs or for one DA.
+/// Counters are `null` for limits that are not configured.
+#[derive(SimpleObject)]
+pub(crate) struct Quota {
+    /// `null` for the limits that count every DA.
+    da: Option<DA>,
+    blobs_per_minute: Option<QuotaCounter>,
+    /// Bytes stored since midnight UTC.
+    bytes_per_day: Option<QuotaCounter>,
+    in_flight: Option<QuotaCounter>,
+    max_blob_size: Option<u64>,
+}
+
+struct Usage {
+    blobs_last_minute: u64,
+    bytes_today: u64,
+    in_flight: u64,
+}
+
+impl Quotas {
+    /// Checks a new blob of `size` bytes against the tenant's limits. On
+    /// success the returned guard must be held until the blob is recorded,
+    /// and the `InFlight` until its submission finishes.
+    pub(crate) async fn admit(
+        &self,
+        api_context: &ApiContext,
+        tenant: Option<&str>,
+        da: DA,
+        size: u64,
+    ) -> Result<(InFlight, MutexGuard<'_, ()>), String> {
+        let admission = self.admission.lock().await;
+        let quota = api_context.settings.quota(tenant);
+        self.check(api_context, &quota.limits, tenant, None, size)?;
+        if let Some(limits) = quota.da.get(da.name()) {
+            self.check(api_context, limits, tenant, Some(da), size)?;
+        }
+
+        let key = (tenant.map(str::to_string), da as u8);
+        *self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default() += 1;
+        let in_flight = InFlight {
+            counts: self.in_flight.clone(),
+            key,
+        };
+        Ok((in_flight, admission))
+    }
+
+    /// Whether a blob already admitted elsewhere could fail over to `da`
+    /// without breaking that DA's own limits.
+    pub(crate) async fn allows_fallback(
+        &self,
+        api_context: &ApiContext,
+        tenant: Option<&str>,
+        da: DA,
+        size: u64,
+    ) -> Result<(), String> {
+        let _admission = self.admission.lock().await;
+        match api_context.settings.quota(tenant).da.get(da.name()) {
+            Some(limits) => self.check(api_context, limits, tenant, Some(da), size),
+            None => Ok(()),
+        }
+    }
+
+    fn check(
+        &self,
+        api_context: &ApiContext,
+        limits: &Limits,
+        tenant: Option<&str>,
+        da: Option<DA>,
+        size: u64,
+    ) -> Result<(), String> {
+        let scope = da.map_or("all DAs", DA::name);
+        if let Some(max) = limits.max_blob_size {
+            if size > max {
+                return Err(format!(
+                    "Blob is {} bytes, over the {} byte limit for {}",
+                    size, max, scope
+                ));
+            }
+        }
+
+        let usage = self.usage(api_context, tenant, da)?;
+        if limits
+            .blobs_per_minute
+            .is_some_and(|max| usage.blobs_last_minute >= max)
+        {
+            return Err(format!("Blobs per minute quota for {} exhausted", scope));
+        }
+        if limits
+            .bytes_per_day
+            .is_some_and(|max| usage.bytes_today + size > max)
+        {
+            return Err(format!("Bytes per day quota for {} exhausted", scope));
+        }
+        if limits
+            .max_in_flight
+            .is_some_and(|max| usage.in_flight >= max)
+        {
+            return Err(format!(
+                "Too many submissions in flight for {}, try again later",
+                scope
+            ));
+        }
+        Ok(())
+    }
+
+    fn usage(
+        &self,
+        api_context: &ApiContext,
+        tenant: Option<&str>,
+        da: Option<DA>,
+    ) -> Result<Usage, String> {
+        let now = unix_time();
+        let index = &api_context.index;
+        let tag = da.map(|da| da as u8);
+        let (blobs_last_minute, _) = index
+            .usage(tenant, tag, now.saturating_sub(MINUTE))
+            .map_err(|e| format!("Could not read usage: {}", e))?;
+        let (_, bytes_today) = index
+            .usage(tenant, tag, now - now % DAY)
+            .map_err(|e| format!("Could not read usage: {}", e))?;
+        let in_flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((t, d), _)| t.as_deref() == tenant && tag.map_or(true, |tag| tag == *d))
+            .map(|(_, count)| count)
+            .sum();
+        Ok(Usage {
+            blobs_last_minute,
+            bytes_today,
+            in_flight,
+        })
+    }
+
+    /// The tenant's limits across all DAs, then for each DA that has its own.
+    pub(crate) fn report(
+        &self,
+        api_context: &ApiContext,
+        tenant: Option<&str>,
+    ) -> Result<Vec<Quota>, String> {
+        let quota = api_context.settings.quota(tenant);
+        let scopes = std::iter::once((None, &quota.limits)).chain(
+            DA::ALL
+                .into_iter()
+                .filter_map(|da| quota.da.get(da.name()).map(|limits| (Some(da), limits))),
+        );
+
+        scopes
+            .map(|(da, limits)| {
+                let usage = self.usage(api_context, tenant, da)?;
+                Ok(Quota {
+                    da,
+                    blobs_per_minute: QuotaCounter::new(
+                        limits.blobs_per_minute,
+                        usage.blobs_last_minute,
+                    ),
+                    bytes_per_day: QuotaCounter::new(limits.bytes_per_day, usage.bytes_today),
+                    in_flight: QuotaCounter::new(limits.max_in_flight, usage.in_flight),
+                    max_blob_size: limits.max_blob_size,
+                })
+            })
+            .collect()
+    }
+}
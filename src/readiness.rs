@@ -0,0 +1,121 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::{avail, celestia, eigen, near, retry::Backend, ApiContext};
+
+/// Outcome of checking one backend.
+#[derive(Serialize)]
+pub(crate) struct ComponentStatus {
+    component: Backend,
+    healthy: bool,
+    /// How long the check took, or the timeout if it did not finish.
+    latency_ms: u64,
+    /// What the backend reported, when healthy.
+    detail: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Readiness {
+    /// Whether the registry and at least one DA are healthy, leaving out
+    /// skipped components. An unhealthy DA alone does not make the service
+    /// unready, since blobs can go to the others.
+    pub(crate) ready: bool,
+    pub(crate) components: Vec<ComponentStatus>,
+}
+
+/// Checks every backend not listed in `ready.skip`, all at once.
+pub(crate) async fn check(api_context: &ApiContext) -> Readiness {
+    let settings = &api_context.settings.ready;
+    let wait = Duration::from_secs(settings.timeout_secs);
+    let max_head_age = settings.max_head_age_secs;
+
+    let (eigenda, avail, celestia, near, registry) = tokio::join!(
+        probe(api_context, Backend::EigenDA, wait, async {
+            let server = &api_context.settings.eigenda.server;
+            eigen::client(server).await.map_err(String::from)?;
+            Ok(format!("connected to {}", server))
+        }),
+        probe(api_context, Backend::Avail, wait, async {
+            let age = avail::finalized_head_age().await?;
+            if age > max_head_age {
+                return Err(format!(
+                    "finalized head is {}s old, over {}s",
+                    age, max_head_age
+                ));
+            }
+            Ok(format!("finalized head is {}s old", age))
+        }),
+        probe(api_context, Backend::Celestia, wait, async {
+            let node = celestia::node_status().await?;
+            if node.height < node.network_height {
+                return Err(format!(
+                    "node is syncing: at height {} of {}",
+                    node.height, node.network_height
+                ));
+            }
+            if node.balance == 0 {
+                return Err("node account has no funds".to_string());
+            }
+            Ok(format!(
+                "synced to height {}, balance {} utia",
+                node.height, node.balance
+            ))
+        }),
+        probe(api_context, Backend::Near, wait, async {
+            let height = near::rpc_status(&api_context.settings.near.network).await?;
+            Ok(format!("latest block {}", height))
+        }),
+        probe(api_context, Backend::Registry, wait, async {
+            match api_context.registry.check().await {
+                Some(result) => result,
+                None => Ok("no remote registry".to_string()),
+            }
+        }),
+    );
+
+    let das: Vec<_> = [eigenda, avail, celestia, near]
+        .into_iter()
+        .flatten()
+        .collect();
+    let ready = registry.as_ref().map_or(true, |status| status.healthy)
+        && (das.is_empty() || das.iter().any(|status| status.healthy));
+    Readiness {
+        ready,
+        components: das.into_iter().chain(registry).collect(),
+    }
+}
+
+/// Runs `check` with a timeout, unless `component` is skipped.
+async fn probe(
+    api_context: &ApiContext,
+    component: Backend,
+    wait: Duration,
+    check: impl Future<Output = Result<String, String>>,
+) -> Option<ComponentStatus> {
+    let skip = &api_context.settings.ready.skip;
+    if skip.iter().any(|name| name == component.name()) {
+        return None;
+    }
+    let started = Instant::now();
+    let result = timeout(wait, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer within {}s", wait.as_secs())));
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let (detail, error) = match result {
+        Ok(detail) => (Some(detail), None),
+        Err(e) => (None, Some(e)),
+    };
+    Some(ComponentStatus {
+        component,
+        healthy: error.is_none(),
+        latency_ms,
+        detail,
+        error,
+    })
+}
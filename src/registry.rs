@@ -0,0 +1,975 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use async_graphql::SimpleObject;
+use async_trait::async_trait;
+use ethers::{
+    contract::{abigen, parse_log},
+    middleware::{
+        gas_oracle::{GasOracleMiddleware, ProviderOracle},
+        SignerMiddleware,
+    },
+    providers::{Http, Middleware, PendingTransaction, Provider},
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes, TransactionReceipt,
+        H256, U256,
+    },
+    utils::hex,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    time::{timeout, timeout_at, Instant},
+};
+
+use crate::{
+    config::{BatchSettings, EvmRegistrySettings, GasSettings, RegistrySettings},
+    index::Index,
+    retry::{self, Backend, Backends, Failure},
+    BlobFilter, MapContract,
+};
+
+abigen!(MapBatchContract, "MapBatch.json");
+abigen!(
+    GasPriceOracle,
+    r#"[function getL1Fee(bytes) external view returns (uint256)]"#
+);
+
+/// Predeploy that prices the L1 data fee on OP-stack chains.
+const GAS_PRICE_ORACLE: &str = "0x420000000000000000000000000000000000000F";
+
+/// Fees come from the node's EIP-1559 estimate; nonces are assigned by
+/// `send`.
+type RegistryClient = SignerMiddleware<
+    GasOracleMiddleware<Provider<Http>, ProviderOracle<Provider<Http>>>,
+    LocalWallet,
+>;
+
+/// Where blob pointers are published. Each deployment picks one through the
+/// `registry` section of the config.
+#[async_trait]
+pub(crate) trait PointerRegistry: Send + Sync {
+    /// Publishes `ptr` and returns the index it was stored at, or `None` when
+    /// the registry does not assign indexes. `prior` is the transaction of an
+    /// earlier write of `ptr` that failed after it was sent; if it was mined
+    /// after all, its result is returned and nothing is sent.
+    async fn save(
+        &self,
+        ptr: Vec<u8>,
+        prior: Option<RegistryTx>,
+    ) -> Result<SavedPointer, RegistryError>;
+
+    /// Reads back the pointer stored at `index`.
+    async fn get(&self, index: [u8; 32]) -> Result<Vec<u8>, String>;
+
+    /// Whether `save` can succeed at all; read-only replicas cannot publish.
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// Wei a `save` of a `len` byte pointer would cost now, or `None` when
+    /// the registry does not charge.
+    async fn estimate_save(&self, _len: usize) -> Result<Option<u128>, String> {
+        Ok(None)
+    }
+
+    /// Address of the account that pays for writes and its balance in wei,
+    /// or `None` when writes are not paid for.
+    async fn balance(&self) -> Option<Result<(String, u128), String>> {
+        None
+    }
+
+    /// Checks that the registry can be reached and written to, describing
+    /// what was seen. `None` when there is nothing remote to check.
+    async fn check(&self) -> Option<Result<String, String>> {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SavedPointer {
+    pub(crate) index: Option<[u8; 32]>,
+    /// The transaction that published the pointer, for on-chain registries.
+    pub(crate) tx: Option<RegistryTx>,
+}
+
+/// A registry write that failed, with the last transaction sent for it, if
+/// any got that far.
+#[derive(Clone, Debug)]
+pub(crate) struct RegistryError {
+    pub(crate) message: String,
+    pub(crate) tx: Option<RegistryTx>,
+}
+
+impl RegistryError {
+    /// Error code reported in metrics: the status of the last transaction
+    /// sent, or `unsent`.
+    pub(crate) fn code(&self) -> String {
+        self.tx
+            .as_ref()
+            .map_or("unsent".to_string(), |tx| tx.status.to_lowercase())
+    }
+}
+
+impl From<String> for RegistryError {
+    fn from(message: String) -> Self {
+        RegistryError { message, tx: None }
+    }
+}
+
+impl From<&str> for RegistryError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<RegistryError> for String {
+    fn from(e: RegistryError) -> Self {
+        e.message
+    }
+}
+
+/// The registry transaction a pointer was published in, stored on the blob
+/// record.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RegistryTx {
+    /// Hash of the last transaction sent; earlier ones were replaced.
+    pub(crate) hash: String,
+    /// `CONFIRMED`, `REVERTED`, `FAILED` or `TIMED_OUT`.
+    pub(crate) status: String,
+    /// How many times the transaction was replaced with higher fees.
+    pub(crate) replacements: u32,
+    /// Wei paid for this blob: the transaction fee, including the L1 data fee
+    /// on OP-stack chains, or an even share of it for batched writes. `null`
+    /// when the transaction was not mined.
+    pub(crate) fee: Option<String>,
+    /// Gas used by the transaction, or this blob's share of a batch.
+    pub(crate) gas_used: Option<u64>,
+    /// Hashes of every transaction sent for the write, oldest first; all but
+    /// the last were replaced.
+    #[serde(default)]
+    pub(crate) sent: Vec<String>,
+}
+
+pub(crate) fn from_settings(
+    settings: &RegistrySettings,
+    index: Arc<Index>,
+    backends: Arc<Backends>,
+) -> Result<Arc<dyn PointerRegistry>, String> {
+    Ok(match settings {
+        RegistrySettings::Evm(evm) => Arc::new(EvmRegistry::new(evm.clone(), backends)?),
+        RegistrySettings::Local => Arc::new(LocalRegistry { index }),
+        RegistrySettings::None => Arc::new(NoopRegistry),
+    })
+}
+
+/// A `MapContract` deployment on any EVM chain.
+pub(crate) struct EvmRegistry {
+    settings: EvmRegistrySettings,
+    /// Shared by every write so the nonce manager sees all of them. `None` on
+    /// read-only replicas, which have no `private_key`.
+    client: Option<Arc<RegistryClient>>,
+    /// Held by `send` from picking a nonce until the node has the
+    /// transaction; shared with the batcher.
+    broadcast: Arc<Mutex<()>>,
+    /// The contract through `rpc` and then each of `read_rpcs`, for lookups.
+    readers: Vec<MapContract<Provider<Http>>>,
+    /// Queue of the background batcher, when `batch` is configured.
+    batcher: Option<mpsc::UnboundedSender<PendingSave>>,
+    backends: Arc<Backends>,
+}
+
+impl EvmRegistry {
+    fn new(settings: EvmRegistrySettings, backends: Arc<Backends>) -> Result<Self, String> {
+        let address = contract_address(&settings)?;
+        let readers: Vec<_> = std::iter::once(&settings.rpc)
+            .chain(&settings.read_rpcs)
+            .map(|rpc| {
+                let provider = Provider::<Http>::try_from(rpc.as_str())
+                    .map_err(|e| format!("Invalid registry RPC url {}: {}", rpc, e))?;
+                Ok(MapContract::new(address, Arc::new(provider)))
+            })
+            .collect::<Result<_, String>>()?;
+
+        let client = match &settings.private_key {
+            Some(key) => Some(client(&settings, key)?),
+            None => None,
+        };
+        let broadcast = Arc::new(Mutex::new(()));
+        let batcher = client
+            .clone()
+            .zip(settings.batch.clone())
+            .map(|(client, batch)| {
+                let (queue, pending) = mpsc::unbounded_channel();
+                tokio::spawn(run_batcher(
+                    settings.clone(),
+                    client,
+                    broadcast.clone(),
+                    backends.clone(),
+                    batch,
+                    queue.clone(),
+                    pending,
+                ));
+                queue
+            });
+        Ok(EvmRegistry {
+            settings,
+            client,
+            broadcast,
+            readers,
+            batcher,
+            backends,
+        })
+    }
+
+    fn client(&self) -> Result<&Arc<RegistryClient>, String> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| "Registry is read-only: no private_key is configured".to_string())
+    }
+
+    /// Fee of a single `save` through `reader`. `save` is owner-only, so the
+    /// call is estimated from the owner: the signer when there is one, or the
+    /// address in the contract's first storage slot on read-only replicas.
+    async fn estimate_through(
+        &self,
+        reader: &MapContract<Provider<Http>>,
+        len: usize,
+    ) -> Result<u128, String> {
+        let provider = reader.client();
+        let owner = match &self.client {
+            Some(client) => client.address(),
+            None => {
+                let slot = provider
+                    .get_storage_at(reader.address(), H256::zero(), None)
+                    .await
+                    .map_err(|e| format!("Registry owner unavailable: {}", e))?;
+                Address::from_slice(&slot.as_bytes()[12..])
+            }
+        };
+        // Non-zero bytes are the dearer calldata.
+        let call = reader.save(vec![0xff; len].into()).from(owner);
+        let gas = call
+            .estimate_gas()
+            .await
+            .map_err(|e| format!("Registry gas estimate failed: {}", e))?;
+        let price = provider
+            .get_gas_price()
+            .await
+            .map_err(|e| format!("Registry gas price unavailable: {}", e))?;
+
+        // Chains without the oracle have no L1 fee.
+        let oracle = GasPriceOracle::new(GAS_PRICE_ORACLE.parse::<Address>().unwrap(), provider);
+        let l1 = oracle
+            .get_l1_fee(call.tx.rlp())
+            .call()
+            .await
+            .unwrap_or_default();
+
+        let fee = gas * price + l1;
+        Ok(fee.try_into().unwrap_or(u128::MAX))
+    }
+
+    /// Each of `rpc` and `read_rpcs` is asked for its chain id. Reads need one
+    /// of them to serve `chain_id`; writes need `rpc` to, and the signer to
+    /// hold funds.
+    async fn check_rpc(&self) -> Result<String, String> {
+        let chain_id = self.settings.chain_id;
+        let rpcs = std::iter::once(&self.settings.rpc).chain(&self.settings.read_rpcs);
+        let mut serving = vec![];
+        let mut errors = vec![];
+        for (reader, rpc) in self.readers.iter().zip(rpcs) {
+            match reader.client().get_chainid().await {
+                Ok(id) if id == chain_id.into() => serving.push(rpc),
+                Ok(id) => errors.push(format!(
+                    "{} serves chain {}, not the configured {}",
+                    rpc, id, chain_id
+                )),
+                Err(e) => errors.push(format!("{} unavailable: {}", rpc, e)),
+            }
+        }
+        let writes_blocked = self.client.is_some() && !serving.contains(&&self.settings.rpc);
+        if serving.is_empty() || writes_blocked {
+            return Err(format!("Registry RPC check failed: {}", errors.join("; ")));
+        }
+        let via = format!("{} of {} RPCs", serving.len(), self.readers.len());
+        let Some(client) = &self.client else {
+            return Ok(format!("chain {} via {}, read-only", chain_id, via));
+        };
+        let signer = client.address();
+        let balance = client
+            .get_balance(signer, None)
+            .await
+            .map_err(|e| format!("Registry signer balance unavailable: {}", e))?;
+        if balance.is_zero() {
+            return Err(format!("Registry signer {:?} has no funds", signer));
+        }
+        Ok(format!(
+            "chain {} via {}, signer {:?} holds {} wei",
+            chain_id, via, signer, balance
+        ))
+    }
+}
+
+fn client(settings: &EvmRegistrySettings, key: &str) -> Result<Arc<RegistryClient>, String> {
+    let owner = key
+        .parse::<LocalWallet>()
+        .map_err(|e| format!("Invalid registry private key: {}", e))?
+        .with_chain_id(settings.chain_id);
+    let provider = Provider::<Http>::try_from(settings.rpc.as_str())
+        .map_err(|e| format!("Invalid registry RPC url: {}", e))?;
+
+    let oracle = ProviderOracle::new(provider.clone());
+    Ok(Arc::new(SignerMiddleware::new(
+        GasOracleMiddleware::new(provider, oracle),
+        owner,
+    )))
+}
+
+fn contract_address(settings: &EvmRegistrySettings) -> Result<Address, String> {
+    settings
+        .contract
+        .parse::<Address>()
+        .map_err(|e| format!("Invalid registry contract address: {}", e))
+}
+
+#[async_trait]
+impl PointerRegistry for EvmRegistry {
+    async fn save(
+        &self,
+        ptr: Vec<u8>,
+        prior: Option<RegistryTx>,
+    ) -> Result<SavedPointer, RegistryError> {
+        if let Some(queue) = &self.batcher {
+            let (reply, saved) = oneshot::channel();
+            queue
+                .send(PendingSave {
+                    ptr,
+                    prior,
+                    attempts: 0,
+                    reply,
+                })
+                .map_err(|_| "Registry batcher stopped")?;
+            return saved.await.map_err(|_| "Registry batcher stopped")?;
+        }
+
+        let client = self.client()?;
+        let address = contract_address(&self.settings)?;
+        if let Some(prior) = &prior {
+            if let Some((receipt, tx)) = mined(client.as_ref(), prior).await? {
+                return saved_pointers(receipt, tx, address, &[ptr]).remove(0);
+            }
+        }
+        let contract = MapContract::new(address, client.clone());
+        let call = contract.save(ptr.clone().into());
+
+        // Only writes that never got a transaction out are retried; once one
+        // is sent, `send` replaces it rather than adding another.
+        let policy = self.backends.policy(Backend::Registry);
+        let mut attempt = 1;
+        let (receipt, tx) = loop {
+            self.backends.admit(Backend::Registry)?;
+            let result = send(
+                client.as_ref(),
+                &self.broadcast,
+                &self.settings.gas,
+                call.tx.clone(),
+            )
+            .await;
+            match result {
+                Ok(sent) => {
+                    self.backends.record(Backend::Registry, true);
+                    break sent;
+                }
+                Err(e) if e.tx.is_none() && attempt < policy.max_attempts => {
+                    self.backends.fail(Backend::Registry, &e.code());
+                    eprintln!("Retrying registry write (attempt {}): {}", attempt + 1, e);
+                    tokio::time::sleep(retry::backoff(&policy, attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    self.backends.fail(Backend::Registry, &e.code());
+                    return Err(e);
+                }
+            }
+        };
+
+        saved_pointers(receipt, tx, address, &[ptr]).remove(0)
+    }
+
+    fn writable(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Gas for a single `save` at the current gas price, plus the L1 data fee
+    /// on OP-stack chains. Batched writes cost less per pointer. Estimated
+    /// through each RPC in turn, so read-only replicas can price writes too.
+    async fn estimate_save(&self, len: usize) -> Result<Option<u128>, String> {
+        let mut errors = vec![];
+        for reader in &self.readers {
+            match self.estimate_through(reader, len).await {
+                Ok(fee) => return Ok(Some(fee)),
+                Err(e) => errors.push(e),
+            }
+        }
+        Err(format!(
+            "Registry cost estimate failed: {}",
+            errors.join("; ")
+        ))
+    }
+
+    async fn check(&self) -> Option<Result<String, String>> {
+        Some(self.check_rpc().await)
+    }
+
+    async fn balance(&self) -> Option<Result<(String, u128), String>> {
+        let client = self.client.as_ref()?;
+        let signer = client.address();
+        let balance = client
+            .get_balance(signer, None)
+            .await
+            .map(|balance| {
+                (
+                    format!("{:?}", signer),
+                    balance.try_into().unwrap_or(u128::MAX),
+                )
+            })
+            .map_err(|e| format!("Registry signer balance unavailable: {}", e));
+        Some(balance)
+    }
+
+    /// Tries each RPC in turn; only a failed call moves on to the next. When
+    /// all of them fail the round is retried.
+    async fn get(&self, index: [u8; 32]) -> Result<Vec<u8>, String> {
+        self.backends
+            .call(Backend::Registry, || async move {
+                let mut errors = vec![];
+                for reader in &self.readers {
+                    match reader.get(index.into()).call().await {
+                        Ok(data) => return Ok(data.0.into()),
+                        Err(e) => errors.push(e.to_string()),
+                    }
+                }
+                Err(Failure::Transient(format!(
+                    "Registry read failed: {}",
+                    errors.join("; ")
+                )))
+            })
+            .await
+    }
+}
+
+/// Sends `tx` and waits for it to be mined. A transaction that is dropped or
+/// not mined within `receipt_timeout_secs` is replaced by one with the same
+/// nonce and fees raised by `fee_bump_percent`, up to `max_replacements`
+/// times. Reverted transactions are not retried.
+async fn send<M: Middleware>(
+    client: &M,
+    lock: &Mutex<()>,
+    gas: &GasSettings,
+    mut tx: TypedTransaction,
+) -> Result<(TransactionReceipt, RegistryTx), RegistryError> {
+    let mut sent: Vec<H256> = vec![];
+    let registry_tx = |sent: &[H256], status: &str| RegistryTx {
+        hash: sent.last().map(hex::encode_prefixed).unwrap_or_default(),
+        status: status.to_string(),
+        replacements: sent.len().saturating_sub(1) as u32,
+        fee: None,
+        gas_used: None,
+        sent: sent.iter().map(hex::encode_prefixed).collect(),
+    };
+
+    for replacement in 0..=gas.max_replacements {
+        // Replacements keep the nonce of the first transaction.
+        let pending = if replacement == 0 {
+            broadcast(client, lock, &mut tx).await
+        } else {
+            bump_fees(&mut tx, gas.fee_bump_percent);
+            client
+                .send_transaction(tx.clone(), None)
+                .await
+                .map_err(|e| e.to_string())
+        };
+        match pending {
+            Ok(pending) => {
+                sent.push(pending.tx_hash());
+                let wait = Duration::from_secs(gas.receipt_timeout_secs);
+                match timeout(wait, pending).await {
+                    Ok(Ok(Some(receipt))) => return checked(receipt, registry_tx(&sent, "")),
+                    Ok(Ok(None)) | Err(_) => {}
+                    Ok(Err(e)) => {
+                        return Err(RegistryError {
+                            message: format!("Registry write failed: {}", e),
+                            tx: Some(registry_tx(&sent, "FAILED")),
+                        })
+                    }
+                }
+            }
+            Err(e) if sent.is_empty() => {
+                return Err(format!("Registry write failed: {}", e).into());
+            }
+            // Usually "nonce too low": an earlier transaction was mined.
+            Err(e) => eprintln!("Registry replacement transaction failed: {}", e),
+        }
+
+        for hash in &sent {
+            if let Ok(Some(receipt)) = client.get_transaction_receipt(*hash).await {
+                let tx = RegistryTx {
+                    hash: hex::encode_prefixed(hash),
+                    ..registry_tx(&sent, "")
+                };
+                return checked(receipt, tx);
+            }
+        }
+    }
+
+    Err(RegistryError {
+        message: format!(
+            "Registry write was not mined after {} replacements",
+            gas.max_replacements
+        ),
+        tx: Some(registry_tx(&sent, "TIMED_OUT")),
+    })
+}
+
+/// Fills in `tx` with the sender's next nonce and broadcasts it. The nonce
+/// is the account's pending transaction count, read under `lock` until the
+/// node has the transaction, so concurrent writes get consecutive nonces and
+/// one that fails before broadcast leaves no gap for later writes to queue
+/// behind.
+async fn broadcast<'a, M: Middleware>(
+    client: &'a M,
+    lock: &Mutex<()>,
+    tx: &mut TypedTransaction,
+) -> Result<PendingTransaction<'a, M::Provider>, String> {
+    let _guard = lock.lock().await;
+    let from = tx
+        .from()
+        .copied()
+        .or_else(|| client.default_sender())
+        .ok_or("Registry write has no sender")?;
+    let nonce = client
+        .get_transaction_count(from, Some(BlockNumber::Pending.into()))
+        .await
+        .map_err(|e| format!("Registry nonce unavailable: {}", e))?;
+    tx.set_nonce(nonce);
+    client
+        .fill_transaction(tx, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    client
+        .send_transaction(tx.clone(), None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Looks for the transactions sent for `prior`. Returns the receipt of the
+/// one that was mined, or `None` when none was, or it reverted, and none is
+/// still pending, so the write can be sent again. Fails while one may still
+/// be mined.
+async fn mined<M: Middleware>(
+    client: &M,
+    prior: &RegistryTx,
+) -> Result<Option<(TransactionReceipt, RegistryTx)>, RegistryError> {
+    let hashes = match prior.sent.is_empty() {
+        true => vec![prior.hash.clone()],
+        false => prior.sent.clone(),
+    };
+    let mut pending = None;
+    for hash in hashes.into_iter().filter(|hash| !hash.is_empty()) {
+        let parsed = hash
+            .parse::<H256>()
+            .map_err(|_| format!("Malformed registry transaction hash {}", hash))?;
+        let receipt = client
+            .get_transaction_receipt(parsed)
+            .await
+            .map_err(|e| format!("Registry receipt lookup failed: {}", e))?;
+        if let Some(receipt) = receipt {
+            // Replacements share a nonce, so no other one can be mined.
+            if receipt.status == Some(0.into()) {
+                return Ok(None);
+            }
+            let tx = RegistryTx {
+                hash,
+                ..prior.clone()
+            };
+            return checked(receipt, tx).map(Some);
+        }
+        let known = client
+            .get_transaction(parsed)
+            .await
+            .map_err(|e| format!("Registry transaction lookup failed: {}", e))?;
+        if known.is_some() {
+            pending = Some(hash);
+        }
+    }
+    match pending {
+        Some(hash) => Err(RegistryError {
+            message: format!("Registry transaction {} is still pending", hash),
+            tx: Some(prior.clone()),
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Fails reverted receipts and sets the final status and fee on `tx`.
+/// Reverted transactions are charged too.
+fn checked(
+    receipt: TransactionReceipt,
+    mut tx: RegistryTx,
+) -> Result<(TransactionReceipt, RegistryTx), RegistryError> {
+    tx.fee = fee(&receipt).map(|fee| fee.to_string());
+    tx.gas_used = receipt.gas_used.map(|gas| gas.low_u64());
+    if receipt.status == Some(0.into()) {
+        tx.status = "REVERTED".to_string();
+        return Err(RegistryError {
+            message: format!("Registry transaction {} reverted", tx.hash),
+            tx: Some(tx),
+        });
+    }
+    tx.status = "CONFIRMED".to_string();
+    Ok((receipt, tx))
+}
+
+/// Gas used at the effective gas price, plus the L1 data fee OP-stack chains
+/// report in `l1Fee`.
+fn fee(receipt: &TransactionReceipt) -> Option<U256> {
+    let execution = receipt.gas_used? * receipt.effective_gas_price?;
+    let l1 = receipt
+        .other
+        .get_deserialized::<U256>("l1Fee")
+        .and_then(Result::ok)
+        .unwrap_or_default();
+    Some(execution + l1)
+}
+
+fn bump_fees(tx: &mut TypedTransaction, percent: u64) {
+    let bump = |fee: U256| fee * (100 + percent) / 100 + 1;
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            inner.max_fee_per_gas = inner.max_fee_per_gas.map(bump);
+            inner.max_priority_fee_per_gas = inner.max_priority_fee_per_gas.map(bump);
+        }
+        _ => {
+            if let Some(price) = tx.gas_price() {
+                tx.set_gas_price(bump(price));
+            }
+        }
+    }
+}
+
+/// A pointer waiting for the batcher, and where to send its index.
+struct PendingSave {
+    ptr: Vec<u8>,
+    /// Transaction of an earlier write of `ptr` that may have been mined.
+    prior: Option<RegistryTx>,
+    /// Batches this pointer has already been part of.
+    attempts: u32,
+    reply: oneshot::Sender<Result<SavedPointer, RegistryError>>,
+}
+
+/// Collects queued pointers into batches of up to `max_size`, waiting at most
+/// `max_wait_ms` after the first one arrives, and publishes each batch with a
+/// single `saveMany`. Pointers whose `Blob` event is missing from the receipt,
+/// or whose transaction failed, go back on the queue after a backoff until
+/// `max_attempts`. While the registry's breaker is open, batches wait for it
+/// without using up attempts.
+async fn run_batcher(
+    settings: EvmRegistrySettings,
+    client: Arc<RegistryClient>,
+    broadcast: Arc<Mutex<()>>,
+    backends: Arc<Backends>,
+    batch: BatchSettings,
+    queue: mpsc::UnboundedSender<PendingSave>,
+    mut pending: mpsc::UnboundedReceiver<PendingSave>,
+) {
+    // Checked by `EvmRegistry::new` before the batcher is started.
+    let address = contract_address(&settings).expect("Invalid registry contract address");
+    while let Some(first) = pending.recv().await {
+        let mut items = vec![first];
+        let deadline = Instant::now() + Duration::from_millis(batch.max_wait_ms);
+        while items.len() < batch.max_size {
+            match timeout_at(deadline, pending.recv()).await {
+                Ok(Some(item)) => items.push(item),
+                _ => break,
+            }
+        }
+
+        // While the breaker is open the batch waits it out; that is not a
+        // failed attempt.
+        if backends.admit(Backend::Registry).is_err() {
+            let wait = backends.open_for(Backend::Registry).unwrap_or_default();
+            requeue(&queue, items, wait);
+            continue;
+        }
+
+        // Pointers whose earlier batch was sent but not seen mined are only
+        // sent again once none of its transactions can still be.
+        let mut results = vec![];
+        let mut batched = vec![];
+        for mut item in items {
+            let Some(prior) = item.prior.take() else {
+                batched.push(item);
+                continue;
+            };
+            match mined(client.as_ref(), &prior).await {
+                Ok(None) => batched.push(item),
+                Ok(Some((receipt, tx))) => {
+                    let mut saved = saved_pointers(receipt, tx, address, &[item.ptr.clone()]);
+                    results.push((item, saved.remove(0)));
+                }
+                Err(e) => {
+                    item.prior = Some(prior);
+                    results.push((item, Err(e)));
+                }
+            }
+        }
+
+        if !batched.is_empty() {
+            let ptrs = batched.iter().map(|item| item.ptr.clone()).collect();
+            let saved = match save_many(&settings, &client, &broadcast, ptrs).await {
+                Ok(saved) => {
+                    backends.record(Backend::Registry, true);
+                    saved
+                }
+                Err(e) => {
+                    backends.fail(Backend::Registry, &e.code());
+                    vec![Err(e); batched.len()]
+                }
+            };
+            results.extend(batched.into_iter().zip(saved));
+        }
+
+        let policy = backends.policy(Backend::Registry);
+        let mut retries = vec![];
+        let mut last_error = None;
+        for (mut item, result) in results {
+            match result {
+                Ok(saved) => {
+                    let _ = item.reply.send(Ok(saved));
+                }
+                Err(e) => {
+                    item.attempts += 1;
+                    if e.tx.is_some() {
+                        item.prior = e.tx.clone();
+                    }
+                    if item.attempts >= batch.max_attempts {
+                        let _ = item.reply.send(Err(e));
+                    } else {
+                        last_error = Some(e);
+                        retries.push(item);
+                    }
+                }
+            }
+        }
+        if let (Some(item), Some(e)) = (retries.first(), last_error) {
+            eprintln!(
+                "Retrying {} registry writes (attempt {}): {}",
+                retries.len(),
+                item.attempts + 1,
+                e
+            );
+            let wait = retry::backoff(&policy, item.attempts);
+            requeue(&queue, retries, wait);
+        }
+    }
+}
+
+/// Puts `items` back on the batcher's queue after `wait`.
+fn requeue(queue: &mpsc::UnboundedSender<PendingSave>, items: Vec<PendingSave>, wait: Duration) {
+    let queue = queue.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(wait).await;
+        for item in items {
+            let _ = queue.send(item);
+        }
+    });
+}
+
+/// Sends `ptrs` in one `saveMany` transaction and returns, for each pointer in
+/// order, the index from its `Blob` event.
+async fn save_many(
+    settings: &EvmRegistrySettings,
+    client: &Arc<RegistryClient>,
+    broadcast: &Mutex<()>,
+    ptrs: Vec<Vec<u8>>,
+) -> Result<Vec<Result<SavedPointer, RegistryError>>, RegistryError> {
+    let address = contract_address(settings)?;
+    let contract = MapBatchContract::new(address, client.clone());
+    let call = contract.save_many(ptrs.iter().cloned().map(Bytes::from).collect());
+    let (receipt, tx) = send(client.as_ref(), broadcast, &settings.gas, call.tx).await?;
+    Ok(saved_pointers(receipt, tx, address, &ptrs))
+}
+
+/// For each of `ptrs` in order, the index from its `Blob` event in `receipt`,
+/// with `tx` billed in even shares to the pointers the transaction saved.
+fn saved_pointers(
+    receipt: TransactionReceipt,
+    tx: RegistryTx,
+    address: Address,
+    ptrs: &[Vec<u8>],
+) -> Vec<Result<SavedPointer, RegistryError>> {
+    let mut events = blob_events(receipt, address);
+
+    let shares = events.len().max(1);
+    let tx = RegistryTx {
+        fee: tx.fee.as_ref().and_then(|fee| {
+            let share = U256::from_dec_str(fee).ok()? / shares;
+            Some(share.to_string())
+        }),
+        gas_used: tx.gas_used.map(|gas| gas / shares as u64),
+        ..tx
+    };
+
+    // The contract emits one event per pointer in input order, but match on
+    // the bytes so a short or reordered log list only fails the affected
+    // pointers.
+    ptrs.iter()
+        .map(|ptr| {
+            let pos = events
+                .iter()
+                .position(|event| event.b.as_ref() == ptr.as_slice())
+                .ok_or_else(|| RegistryError {
+                    message: format!(
+                        "Transaction {} emitted no Blob event for this pointer",
+                        tx.hash
+                    ),
+                    tx: Some(tx.clone()),
+                })?;
+            Ok(SavedPointer {
+                index: Some(index_of(&events.remove(pos))),
+                tx: Some(tx.clone()),
+            })
+        })
+        .collect()
+}
+
+/// `Blob` events in `receipt` emitted by the registry contract at `address`,
+/// in log order. Logs from other contracts and other events are skipped.
+fn blob_events(receipt: TransactionReceipt, address: Address) -> Vec<BlobFilter> {
+    receipt
+        .logs
+        .into_iter()
+        .filter(|log| log.address == address)
+        .filter_map(|log| parse_log::<BlobFilter>(log).ok())
+        .collect()
+}
+
+/// The event's map index, in the 32-byte form returned to clients.
+pub(crate) fn index_of(event: &BlobFilter) -> [u8; 32] {
+    let mut index = [0u8; 32];
+    event.id.to_big_endian(&mut index);
+    index
+}
+
+/// Keeps pointers in the local database only, numbered like the contract's
+/// ids. Nothing is published on chain.
+pub(crate) struct LocalRegistry {
+    index: Arc<Index>,
+}
+
+#[async_trait]
+impl PointerRegistry for LocalRegistry {
+    async fn save(
+        &self,
+        ptr: Vec<u8>,
+        _prior: Option<RegistryTx>,
+    ) -> Result<SavedPointer, RegistryError> {
+        let id = self
+            .index
+            .save_pointer(&ptr)
+            .map_err(|e| format!("Registry write failed: {}", e))?;
+
+        let mut index = [0u8; 32];
+        index[24..].copy_from_slice(&id.to_be_bytes());
+        Ok(SavedPointer {
+            index: Some(index),
+            tx: None,
+        })
+    }
+
+    async fn get(&self, index: [u8; 32]) -> Result<Vec<u8>, String> {
+        if index[..24].iter().any(|&b| b != 0) {
+            return Err("Not found".to_string());
+        }
+        let id = u64::from_be_bytes(index[24..].try_into().unwrap());
+        self.index
+            .pointer(id)
+            .map_err(|e| format!("Registry read failed: {}", e))?
+            .ok_or_else(|| "Not found".to_string())
+    }
+}
+
+/// Publishes nothing; pointers live only in the blob index.
+pub(crate) struct NoopRegistry;
+
+#[async_trait]
+impl PointerRegistry for NoopRegistry {
+    async fn save(
+        &self,
+        _ptr: Vec<u8>,
+        _prior: Option<RegistryTx>,
+    ) -> Result<SavedPointer, RegistryError> {
+        Ok(SavedPointer {
+            index: None,
+            tx: None,
+        })
+    }
+
+    async fn get(&self, _index: [u8; 32]) -> Result<Vec<u8>, String> {
+        Err("No pointer registry is configured".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::{JsonRpcError, MockProvider, MockResponse},
+        types::TransactionRequest,
+    };
+
+    use super::*;
+
+    /// A legacy transfer with gas and price set, so filling it sends nothing.
+    fn transfer() -> TypedTransaction {
+        TransactionRequest::new()
+            .from(Address::repeat_byte(1))
+            .to(Address::repeat_byte(2))
+            .gas(21_000)
+            .gas_price(1)
+            .into()
+    }
+
+    fn reject(mock: &MockProvider) {
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: "insufficient funds for gas * price + value".to_string(),
+            data: None,
+        }));
+    }
+
+    #[tokio::test]
+    async fn failed_broadcast_leaves_no_nonce_gap() {
+        let (provider, mock) = Provider::mocked();
+        let lock = Mutex::new(());
+
+        // The mock answers the last response pushed first.
+        reject(&mock);
+        mock.push(U256::from(7)).unwrap();
+        let mut failed = transfer();
+        assert!(broadcast(&provider, &lock, &mut failed).await.is_err());
+
+        mock.push(H256::repeat_byte(3)).unwrap();
+        mock.push(U256::from(7)).unwrap();
+        let mut next = transfer();
+        let pending = broadcast(&provider, &lock, &mut next).await.unwrap();
+        assert_eq!(pending.tx_hash(), H256::repeat_byte(3));
+        assert_eq!(next.nonce(), Some(&U256::from(7)));
+    }
+}
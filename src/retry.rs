@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_graphql::{Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::RetryPolicy, metrics::Metrics, unix_time, DA};
+
+/// An external service calls are made to.
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Backend {
+    EigenDA,
+    Avail,
+    Near,
+    Celestia,
+    /// The EVM registry's RPC.
+    Registry,
+}
+
+impl Backend {
+    pub(crate) const ALL: [Backend; 5] = [
+        Backend::EigenDA,
+        Backend::Avail,
+        Backend::Near,
+        Backend::Celestia,
+        Backend::Registry,
+    ];
+
+    /// Name used for the backend in config files.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Backend::EigenDA => "eigenda",
+            Backend::Avail => "avail",
+            Backend::Near => "near",
+            Backend::Celestia => "celestia",
+            Backend::Registry => "registry",
+        }
+    }
+}
+
+impl From<DA> for Backend {
+    fn from(da: DA) -> Self {
+        match da {
+            DA::EigenDA => Backend::EigenDA,
+            DA::Avail => Backend::Avail,
+            DA::Near => Backend::Near,
+            DA::Celestia => Backend::Celestia,
+        }
+    }
+}
+
+/// Why a backend call failed, which decides whether it is retried and
+/// whether it counts against the backend's circuit breaker.
+#[derive(Debug)]
+pub(crate) enum Failure {
+    /// Nothing took effect, e.g. the connection failed or the call was a
+    /// read, so repeating it is safe.
+    Transient(String),
+    /// The request may have taken effect; repeating it could post the blob
+    /// twice.
+    Final(String),
+    /// The request itself was bad. Not retried and not held against the
+    /// backend.
+    Invalid(String),
+}
+
+impl Failure {
+    /// Error code reported in metrics.
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Failure::Transient(_) => "transient",
+            Failure::Final(_) => "final",
+            Failure::Invalid(_) => "invalid",
+        }
+    }
+}
+
+impl From<Failure> for String {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Transient(e) | Failure::Final(e) | Failure::Invalid(e) => e,
+        }
+    }
+}
+
+/// One submission of a blob to a DA, recorded on the blob before the
+/// transaction is sent. Before sending the blob to the same DA again, a
+/// retry or failover looks for the transaction of the last attempt.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Attempt {
+    pub(crate) da: DA,
+    /// Hex hash of the transaction, when it is known before the DA answers.
+    pub(crate) tx_hash: Option<String>,
+    /// Chain height when the attempt was made; it can only land above it.
+    pub(crate) height: Option<u64>,
+    /// Account nonce the transaction was signed with.
+    pub(crate) nonce: Option<u64>,
+    /// Unix seconds.
+    pub(crate) at: u64,
+}
+
+impl Attempt {
+    /// An attempt about to be made on `da`, with nothing known yet about its
+    /// transaction.
+    pub(crate) fn new(da: DA) -> Self {
+        Attempt {
+            da,
+            tx_hash: None,
+            height: None,
+            nonce: None,
+            at: unix_time(),
+        }
+    }
+}
+
+/// Whether an earlier attempt's transaction made it onto the DA.
+pub(crate) enum Landed<T> {
+    Yes(T),
+    /// It did not, and no longer can.
+    No,
+    /// It may still land; sending the blob again could post it twice.
+    Unknown(String),
+}
+
+impl<T> Landed<T> {
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> Landed<U> {
+        match self {
+            Landed::Yes(value) => Landed::Yes(f(value)),
+            Landed::No => Landed::No,
+            Landed::Unknown(e) => Landed::Unknown(e),
+        }
+    }
+}
+
+/// Circuit breaker state of one backend. It opens after `failure_threshold`
+/// failures in a row and refuses calls for `cooldown_secs`; calls are then
+/// let through again, and the first failure reopens it.
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// A backend's breaker, as reported by `/health`.
+#[derive(Serialize)]
+pub(crate) struct BackendHealth {
+    pub(crate) backend: Backend,
+    /// `closed`, `open` or `half_open`.
+    pub(crate) state: &'static str,
+    /// Failed calls since the last success.
+    pub(crate) consecutive_failures: u32,
+    /// Seconds until an open breaker lets calls through again.
+    pub(crate) retry_in_secs: Option<u64>,
+}
+
+/// Retry policies and circuit breakers for every backend, shared by all
+/// calls.
+pub(crate) struct Backends {
+    policies: HashMap<String, RetryPolicy>,
+    breakers: Mutex<HashMap<Backend, Breaker>>,
+    metrics: Arc<Metrics>,
+}
+
+impl Backends {
+    pub(crate) fn new(policies: HashMap<String, RetryPolicy>, metrics: Arc<Metrics>) -> Self {
+        Backends {
+            policies,
+            breakers: Mutex::new(HashMap::new()),
+            metrics,
+        }
+    }
+
+    pub(crate) fn policy(&self, backend: Backend) -> RetryPolicy {
+        self.policies
+            .get(backend.name())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Runs `call`, retrying transient failures with exponential backoff and
+    /// full jitter up to the backend's `max_attempts`. Fails straight away
+    /// while the backend's breaker is open.
+    pub(crate) async fn call<T, F, Fut>(&self, backend: Backend, call: F) -> Result<T, String>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        self.try_call(backend, call).await.map_err(String::from)
+    }
+
+    /// Like `call`, keeping how the last try failed. An open breaker is a
+    /// transient failure, since nothing was sent.
+    pub(crate) async fn try_call<T, F, Fut>(
+        &self,
+        backend: Backend,
+        mut call: F,
+    ) -> Result<T, Failure>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        let policy = self.policy(backend);
+        let mut attempt = 1;
+        loop {
+            self.admit(backend).map_err(Failure::Transient)?;
+            let failure = match call().await {
+                Ok(value) => {
+                    self.record(backend, true);
+                    return Ok(value);
+                }
+                Err(failure) => failure,
+            };
+            self.metrics.error(backend, failure.code());
+            if let Failure::Invalid(_) = failure {
+                return Err(failure);
+            }
+            self.record(backend, false);
+            match failure {
+                Failure::Transient(e) if attempt < policy.max_attempts => {
+                    eprintln!(
+                        "{} call failed (attempt {} of {}), retrying: {}",
+                        backend.name(),
+                        attempt,
+                        policy.max_attempts,
+                        e
+                    );
+                    tokio::time::sleep(backoff(&policy, attempt)).await;
+                    attempt += 1;
+                }
+                failure => return Err(failure),
+            }
+        }
+    }
+
+    /// Fails while `backend`'s breaker is open.
+    pub(crate) fn check(&self, backend: Backend) -> Result<(), String> {
+        match self.open_for(backend) {
+            Some(left) => Err(format!(
+                "{} is unavailable after repeated failures; retry in {}s",
+                backend.name(),
+                left.as_secs() + 1
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// How much longer `backend`'s breaker stays open, if it is open.
+    pub(crate) fn open_for(&self, backend: Backend) -> Option<Duration> {
+        let breakers = self.breakers.lock().unwrap();
+        let until = breakers.get(&backend).and_then(|b| b.open_until)?;
+        let now = Instant::now();
+        (now < until).then(|| until - now)
+    }
+
+    /// Like `check`, for a call about to be made; refusals are counted in
+    /// metrics.
+    pub(crate) fn admit(&self, backend: Backend) -> Result<(), String> {
+        self.check(backend).map_err(|e| {
+            self.metrics.error(backend, "circuit_open");
+            e
+        })
+    }
+
+    /// Counts a failed call under `code` in metrics and against `backend`'s
+    /// breaker.
+    pub(crate) fn fail(&self, backend: Backend, code: &str) {
+        self.metrics.error(backend, code);
+        self.record(backend, false);
+    }
+
+    /// Counts a call's outcome towards `backend`'s breaker.
+    pub(crate) fn record(&self, backend: Backend, success: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(backend).or_default();
+        if success {
+            *breaker = Breaker::default();
+            return;
+        }
+        breaker.failures += 1;
+        let policy = self.policy(backend);
+        if breaker.failures >= policy.failure_threshold {
+            if breaker.open_until.is_none() {
+                eprintln!(
+                    "{} circuit breaker opened after {} failures",
+                    backend.name(),
+                    breaker.failures
+                );
+            }
+            breaker.open_until = Some(Instant::now() + Duration::from_secs(policy.cooldown_secs));
+        }
+    }
+
+    /// Whether calls to `backend` are currently refused.
+    pub(crate) fn is_open(&self, backend: Backend) -> bool {
+        self.check(backend).is_err()
+    }
+
+    pub(crate) fn report(&self) -> Vec<BackendHealth> {
+        let breakers = self.breakers.lock().unwrap();
+        let now = Instant::now();
+        Backend::ALL
+            .into_iter()
+            .map(|backend| {
+                let breaker = breakers.get(&backend);
+                let open_until = breaker.and_then(|b| b.open_until);
+                let state = match open_until {
+                    Some(until) if now < until => "open",
+                    Some(_) => "half_open",
+                    None => "closed",
+                };
+                BackendHealth {
+                    backend,
+                    state,
+                    consecutive_failures: breaker.map_or(0, |b| b.failures),
+                    retry_in_secs: open_until
+                        .filter(|&until| now < until)
+                        .map(|until| until.saturating_duration_since(now).as_secs() + 1),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Delay before retry number `attempt`: uniform between zero and
+/// `base_delay_ms * 2^(attempt - 1)`, capped at `max_delay_ms`.
+pub(crate) fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let ceiling = policy
+        .base_delay_ms
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(policy.max_delay_ms);
+    // The clock's nanoseconds are random enough to spread out retries.
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u64;
+    Duration::from_millis(nanos % (ceiling + 1))
+}
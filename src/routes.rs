@@ -1,33 +1,101 @@
-use crate::model::ServiceSchema;
+use std::sync::Arc;
+
+use crate::{
+    auth, billing, config::Settings, metrics, model::ServiceSchema, readiness,
+    retry::BackendHealth, ApiContext,
+};
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
 use axum::{
-    extract::Extension,
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    extract::{Extension, Query},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
     Json,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+/// Liveness: answers without contacting any backend.
 #[derive(Serialize)]
 struct Health {
     healthy: bool,
+    /// Circuit breaker of each backend.
+    backends: Vec<BackendHealth>,
 }
 
-pub(crate) async fn health() -> impl IntoResponse {
-    let health = Health { healthy: true };
+pub(crate) async fn health(api_context: Extension<ApiContext>) -> impl IntoResponse {
+    let health = Health {
+        healthy: true,
+        backends: api_context.backends.report(),
+    };
     (StatusCode::OK, Json(health))
 }
 
+/// Readiness probe: checks every backend and answers 503 unless all of them
+/// are healthy.
+pub(crate) async fn ready(api_context: Extension<ApiContext>) -> impl IntoResponse {
+    let readiness = readiness::check(&api_context).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// Prometheus scrape endpoint.
+pub(crate) async fn metrics(api_context: Extension<ApiContext>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&api_context).await,
+    )
+}
+
 pub(crate) async fn graphql_playground() -> impl IntoResponse {
     Html(playground_source(
         GraphQLPlaygroundConfig::new("/").subscription_endpoint("/ws"),
     ))
 }
 
+/// Runs a GraphQL request on behalf of the caller identified by its headers.
+/// Bad credentials are rejected here; what anonymous callers may do is
+/// decided by the resolvers.
 pub(crate) async fn graphql_handler(
     schema: Extension<ServiceSchema>,
+    settings: Extension<Arc<Settings>>,
+    headers: HeaderMap,
     req: GraphQLRequest,
-) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
+) -> Response {
+    let caller = match auth::authenticate(&settings, &headers) {
+        Ok(caller) => caller,
+        Err(e) => return (StatusCode::UNAUTHORIZED, e).into_response(),
+    };
+    GraphQLResponse::from(schema.execute(req.into_inner().data(caller)).await).into_response()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UsageParams {
+    from: Option<u64>,
+    to: Option<u64>,
+    tenant: Option<String>,
+}
+
+/// The `usage` report as CSV, authenticated like GraphQL requests.
+pub(crate) async fn usage_csv(
+    api_context: Extension<ApiContext>,
+    headers: HeaderMap,
+    Query(params): Query<UsageParams>,
+) -> Response {
+    let settings = &api_context.settings;
+    let caller = match auth::authenticate(settings, &headers) {
+        Ok(caller) => caller,
+        Err(e) => return (StatusCode::UNAUTHORIZED, e).into_response(),
+    };
+    let tenant = match auth::scope(settings, &caller, params.tenant) {
+        Ok(tenant) => tenant,
+        Err(e) => return (StatusCode::FORBIDDEN, e).into_response(),
+    };
+    match billing::usage(&api_context, tenant.as_deref(), params.from, params.to) {
+        Ok(usage) => ([(CONTENT_TYPE, "text/csv")], billing::csv(&usage)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
 }
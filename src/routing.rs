@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{DaProfile, RoutingPreference, RoutingSettings},
+    estimate, unix_time, ApiContext, DA,
+};
+
+/// Finished submissions needed before a DA's failure rate counts.
+const MIN_SAMPLES: u64 = 3;
+
+/// Why `storeBlob(da: AUTO)` picked the DA it did, kept on the blob record.
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RoutingDecision {
+    /// `cheapest`, `fastest`, `healthiest` or `order`.
+    pub(crate) prefer: String,
+    /// Why the chosen DA ranked first.
+    pub(crate) reason: String,
+    /// Candidates ruled out by the policy, each with the limit it broke.
+    pub(crate) rejected: Vec<String>,
+}
+
+/// A candidate that passed every limit.
+struct Candidate {
+    da: DA,
+    latency_secs: u64,
+    failure_rate: f64,
+    /// Finished submissions `failure_rate` is based on.
+    samples: u64,
+    cost_usd: Option<f64>,
+}
+
+/// Built-in latency, size limit and security rank of each DA.
+fn profile(da: DA, overrides: Option<&DaProfile>) -> (u64, u64, u8) {
+    let (latency_secs, max_blob_size) = match da {
+        // Inclusion in a ~12s block.
+        DA::Celestia => (15, 1_900_000),
+        // Submissions wait for finality.
+        DA::Avail => (60, 512 * 1024),
+        // Function call arguments are limited to 4 MiB.
+        DA::Near => (5, 4_000_000),
+        // Dispersal is confirmed on Ethereum, in batches.
+        DA::EigenDA => (600, 2 * 1024 * 1024),
+    };
+    let overrides = overrides.cloned().unwrap_or_default();
+    (
+        overrides.latency_secs.unwrap_or(latency_secs),
+        overrides.max_blob_size.unwrap_or(max_blob_size),
+        overrides.security.unwrap_or(1),
+    )
+}
+
+/// Ranks the DAs eligible for a blob of `size` bytes under the tenant's
+/// routing policy, best first. The first is the one picked; the rest are
+/// where a failed submission goes next.
+pub(crate) async fn choose(
+    api_context: &ApiContext,
+    tenant: Option<&str>,
+    size: u64,
+) -> Result<(Vec<DA>, RoutingDecision), String> {
+    let policy = api_context.settings.routing(tenant);
+    let names: Vec<_> = if policy.candidates.is_empty() {
+        DA::ALL.to_vec()
+    } else {
+        policy
+            .candidates
+            .iter()
+            .filter_map(|name| DA::from_name(name))
+            .collect()
+    };
+
+    let mut rejected = vec![];
+    let mut candidates = vec![];
+    let since = unix_time().saturating_sub(policy.health_window_secs);
+    for da in names {
+        let (latency_secs, max_blob_size, security) = profile(da, policy.da.get(da.name()));
+        if size > max_blob_size {
+            rejected.push(format!(
+                "{}: {} bytes is over its {} byte limit",
+                da.name(),
+                size,
+                max_blob_size
+            ));
+            continue;
+        }
+        if security < policy.min_security {
+            rejected.push(format!(
+                "{}: security rank {} is below {}",
+                da.name(),
+                security,
+                policy.min_security
+            ));
+            continue;
+        }
+        if let Some(max) = policy.max_latency_secs.filter(|&max| latency_secs > max) {
+            rejected.push(format!(
+                "{}: expected latency {}s is over {}s",
+                da.name(),
+                latency_secs,
+                max
+            ));
+            continue;
+        }
+        if da == DA::Celestia && api_context.settings.celestia.gas_price.is_none() {
+            rejected.push("celestia: celestia.gas_price is not set".to_string());
+            continue;
+        }
+        if api_context.backends.is_open(da.into()) {
+            rejected.push(format!("{}: circuit breaker is open", da.name()));
+            continue;
+        }
+
+        let (samples, failed) = api_context
+            .index
+            .outcomes(da as u8, since)
+            .map_err(|e| format!("Could not read DA health: {}", e))?;
+        let failure_rate = if samples == 0 {
+            0.0
+        } else {
+            failed as f64 / samples as f64
+        };
+        if samples >= MIN_SAMPLES && failure_rate > policy.max_failure_rate {
+            rejected.push(format!(
+                "{}: {} of the last {} submissions failed",
+                da.name(),
+                failed,
+                samples
+            ));
+            continue;
+        }
+        candidates.push(Candidate {
+            da,
+            latency_secs,
+            failure_rate,
+            samples,
+            cost_usd: None,
+        });
+    }
+
+    // Without `prices` no estimate has a total, so `cheapest` falls back to
+    // candidate order; `Settings::load` refuses a ceiling without them.
+    let priced = !api_context.settings.prices.is_empty();
+    if priced && (policy.max_cost_usd.is_some() || policy.prefer == RoutingPreference::Cheapest) {
+        price(
+            api_context,
+            tenant,
+            policy,
+            size,
+            &mut candidates,
+            &mut rejected,
+        )
+        .await;
+    }
+
+    match policy.prefer {
+        RoutingPreference::Cheapest => candidates.sort_by(|a, b| match (a.cost_usd, b.cost_usd) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        }),
+        RoutingPreference::Fastest => candidates.sort_by_key(|c| c.latency_secs),
+        // Too few samples say nothing about health, so those DAs follow the
+        // proven ones, in candidate order.
+        RoutingPreference::Healthiest => candidates.sort_by(|a, b| {
+            (a.samples < MIN_SAMPLES)
+                .cmp(&(b.samples < MIN_SAMPLES))
+                .then(a.failure_rate.total_cmp(&b.failure_rate))
+        }),
+        RoutingPreference::Order => {}
+    }
+
+    let Some(chosen) = candidates.first() else {
+        return Err(format!(
+            "No DA satisfies the routing policy: {}",
+            rejected.join("; ")
+        ));
+    };
+    let reason = match policy.prefer {
+        RoutingPreference::Cheapest => match chosen.cost_usd {
+            Some(cost) => format!("cheapest eligible DA at an estimated ${:.6}", cost),
+            None => "no eligible DA could be priced; first in candidate order".to_string(),
+        },
+        RoutingPreference::Fastest => format!(
+            "fastest eligible DA, about {}s until final",
+            chosen.latency_secs
+        ),
+        RoutingPreference::Healthiest if chosen.samples < MIN_SAMPLES => format!(
+            "no eligible DA has {} recent submissions; first in candidate order",
+            MIN_SAMPLES
+        ),
+        RoutingPreference::Healthiest => format!(
+            "healthiest eligible DA, {:.0}% of {} recent submissions failed",
+            chosen.failure_rate * 100.0,
+            chosen.samples
+        ),
+        RoutingPreference::Order => "first eligible DA in candidate order".to_string(),
+    };
+    let prefer = match policy.prefer {
+        RoutingPreference::Cheapest => "cheapest",
+        RoutingPreference::Fastest => "fastest",
+        RoutingPreference::Healthiest => "healthiest",
+        RoutingPreference::Order => "order",
+    };
+    Ok((
+        candidates.iter().map(|candidate| candidate.da).collect(),
+        RoutingDecision {
+            prefer: prefer.to_string(),
+            reason,
+            rejected,
+        },
+    ))
+}
+
+/// Fills in each candidate's estimated cost and drops those over
+/// `max_cost_usd`, or that cannot be priced when a ceiling is set.
+async fn price(
+    api_context: &ApiContext,
+    tenant: Option<&str>,
+    policy: &RoutingSettings,
+    size: u64,
+    candidates: &mut Vec<Candidate>,
+    rejected: &mut Vec<String>,
+) {
+    let das: Vec<_> = candidates.iter().map(|c| c.da).collect();
+    let costs: HashMap<_, _> = estimate::compare(api_context, tenant, &das, size)
+        .await
+        .into_iter()
+        .map(|estimate| (estimate.da as u8, estimate))
+        .collect();
+
+    candidates.retain_mut(|candidate| {
+        let estimate = costs.get(&(candidate.da as u8));
+        candidate.cost_usd = estimate.and_then(|estimate| estimate.total_usd);
+        let Some(max) = policy.max_cost_usd else {
+            return true;
+        };
+        match candidate.cost_usd {
+            Some(cost) if cost > max => {
+                rejected.push(format!(
+                    "{}: estimated ${:.6} is over ${}",
+                    candidate.da.name(),
+                    cost,
+                    max
+                ));
+                false
+            }
+            Some(_) => true,
+            None => {
+                let why = estimate
+                    .map(|estimate| estimate.errors.join(", "))
+                    .filter(|errors| !errors.is_empty())
+                    .unwrap_or_else(|| "no price for its tokens".to_string());
+                rejected.push(format!("{}: cost unknown ({})", candidate.da.name(), why));
+                false
+            }
+        }
+    });
+}